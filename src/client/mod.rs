@@ -52,10 +52,10 @@ impl RawClientWrapper {
         }
     }
 
-    pub async fn put(&self, key: Key, val: &str) -> Result<(), Error> {
+    pub async fn put(&self, key: Key, val: Value) -> Result<(), Error> {
         let mut last_err: Option<Error> = None;
         for i in 0..self.retries {
-            match self.client.put(key.clone(), val.to_owned()).await {
+            match self.client.put(key.clone(), val.clone()).await {
                 Ok(_) => {
                     return Ok(());
                 }
@@ -125,7 +125,6 @@ impl RawClientWrapper {
                     return Ok((val, swapped));
                 }
                 Err(err) => {
-                    println!("Err: {:?}", &err);
                     if self.error_retryable(&err) {
                         last_err.replace(err);
                         sleep(std::cmp::min(2 + i, 200)).await;
@@ -1,8 +1,10 @@
 use crate::{
     commands::asyncs::admin::*,
-    utils::async_execute,
+    encoding::{KeyEncoder, KeyDecoder, DecodedKey},
+    utils::{async_execute, get_selected_db, NextBytes},
 };
-use redis_module::{Context, NextArg, RedisError, RedisResult, RedisString};
+use std::str::FromStr;
+use redis_module::{Context, NextArg, RedisError, RedisResult, RedisString, RedisValue};
 use tikv_client::{ColumnFamily, Error};
 
 pub fn tikv_rawkv_dscan(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
@@ -118,4 +120,162 @@ pub fn tikv_rawkv_cfscan(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
             do_async_rawkv_ascan_range(cf, start_key, end_key, limit).await
         }
     })
-}
\ No newline at end of file
+}
+
+pub fn tikv_migrate(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    let mut args = args.into_iter().skip(1);
+    let limit = match args.next() {
+        Some(s) => {
+            let arg = s.try_as_str()?;
+            if arg.eq_ignore_ascii_case("status") {
+                return async_execute(ctx, async move {
+                    do_async_format_version().await
+                });
+            }
+            if arg.eq_ignore_ascii_case("skipped") {
+                let limit = match args.next() {
+                    Some(s) => u64::from_str(s.try_as_str()?)?,
+                    None => 1000,
+                };
+                return async_execute(ctx, async move {
+                    do_async_migrate_skipped(limit).await
+                });
+            }
+            u64::from_str(arg)?
+        }
+        None => 1000,
+    };
+    if limit == 0 {
+        return Err(RedisError::Str("limit should be greater than 0"));
+    }
+    async_execute(ctx, async move {
+        do_async_migrate(limit).await
+    })
+}
+
+pub fn tikv_flushdb(ctx: &Context, _args: Vec<RedisString>) -> RedisResult {
+    async_execute(ctx, async move {
+        do_async_flushdb().await
+    })
+}
+
+pub fn tikv_swapdb(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    if args.len() < 3 {
+        return Err(RedisError::WrongArity);
+    }
+    let mut args = args.into_iter().skip(1);
+    let db1 = args.next_u64()?;
+    let db2 = args.next_u64()?;
+    if db1 >= u32::MAX as u64 || db2 >= u32::MAX as u64 {
+        return Err(RedisError::Str("DB index is out of range"));
+    }
+    async_execute(ctx, async move {
+        do_async_swapdb(db1 as u32, db2 as u32).await
+    })
+}
+
+// Print the TiKV key of given data type and user key in client's selected DB.
+// Without field (or index) Hash and Set returns the start key of the key's
+// data range and List returns the meta key.
+pub fn tikv_encode_key(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    if args.len() < 3 {
+        return Err(RedisError::WrongArity);
+    }
+    let mut args = args.into_iter().skip(1);
+    let tp = args.next_str()?.to_lowercase();
+    let key = args.next_bytes()?;
+    let sub = args.next();
+    let encoder = KeyEncoder::with_db(get_selected_db(ctx));
+    let ekey = match (tp.as_str(), sub) {
+        ("string", None) => encoder.encode_string(&key),
+        ("type", None) => encoder.encode_type_key(&key),
+        ("hash", Some(field)) => encoder.encode_hash(&key, field.as_slice()),
+        ("hash", None) => encoder.encode_hash_start(&key),
+        ("set", Some(member)) => encoder.encode_set(&key, member.as_slice()),
+        ("set", None) => encoder.encode_set_start(&key),
+        ("list", Some(idx)) => encoder.encode_list_elem_key(&key, idx.parse_integer()?),
+        ("list", None) => encoder.encode_list_meta_key(&key),
+        ("counter", Some(shard)) => encoder.encode_counter_shard(&key, shard.parse_integer()? as u32),
+        ("counter", None) => encoder.encode_counter_start(&key),
+        ("string", Some(_)) | ("type", Some(_)) => return Err(RedisError::WrongArity),
+        _ => return Err(RedisError::Str("Unknown data type, should be string, hash, list, set, counter or type")),
+    };
+    Ok(Into::<Vec<u8>>::into(ekey).into())
+}
+
+// Print all parts of a raw TiKV key as field value pairs
+pub fn tikv_decode_key(_ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    if args.len() < 2 {
+        return Err(RedisError::WrongArity);
+    }
+    let mut args = args.into_iter().skip(1);
+    let rkey = args.next_bytes()?;
+    let info = match KeyDecoder::new().decode_key(&rkey) {
+        Some(info) => info,
+        None => return Err(RedisError::Str("Not a RedisTiKV key")),
+    };
+    let mut ret: Vec<RedisValue> = vec![
+        RedisValue::SimpleStringStatic("instanceid"),
+        RedisValue::Integer(info.instance_id as i64),
+        RedisValue::SimpleStringStatic("version"),
+        RedisValue::Integer(info.version as i64),
+        RedisValue::SimpleStringStatic("db"),
+        match info.db {
+            Some(db) => RedisValue::Integer(db as i64),
+            None => RedisValue::Null,
+        },
+        RedisValue::SimpleStringStatic("type"),
+    ];
+    let parts: Vec<RedisValue> = match info.key {
+        DecodedKey::String(key) => vec![
+            RedisValue::SimpleStringStatic("string"),
+            RedisValue::SimpleStringStatic("key"), key.into(),
+        ],
+        DecodedKey::StringChunk(key, gen, idx) => vec![
+            RedisValue::SimpleStringStatic("string"),
+            RedisValue::SimpleStringStatic("key"), key.into(),
+            RedisValue::SimpleStringStatic("chunkgen"), RedisValue::Integer(gen as i64),
+            RedisValue::SimpleStringStatic("chunk"), RedisValue::Integer(idx as i64),
+        ],
+        DecodedKey::Hash(key, field) => vec![
+            RedisValue::SimpleStringStatic("hash"),
+            RedisValue::SimpleStringStatic("key"), key.into(),
+            RedisValue::SimpleStringStatic("field"), field.into(),
+        ],
+        DecodedKey::HashMeta(key) => vec![
+            RedisValue::SimpleStringStatic("hash"),
+            RedisValue::SimpleStringStatic("key"), key.into(),
+            RedisValue::SimpleStringStatic("meta"), RedisValue::Integer(1),
+        ],
+        DecodedKey::ListMeta(key) => vec![
+            RedisValue::SimpleStringStatic("list"),
+            RedisValue::SimpleStringStatic("key"), key.into(),
+            RedisValue::SimpleStringStatic("meta"), RedisValue::Integer(1),
+        ],
+        DecodedKey::ListElem(key, idx) => vec![
+            RedisValue::SimpleStringStatic("list"),
+            RedisValue::SimpleStringStatic("key"), key.into(),
+            RedisValue::SimpleStringStatic("index"), RedisValue::Integer(idx),
+        ],
+        DecodedKey::Set(key, member) => vec![
+            RedisValue::SimpleStringStatic("set"),
+            RedisValue::SimpleStringStatic("key"), key.into(),
+            RedisValue::SimpleStringStatic("member"), member.into(),
+        ],
+        DecodedKey::CounterShard(key, shard) => vec![
+            RedisValue::SimpleStringStatic("counter"),
+            RedisValue::SimpleStringStatic("key"), key.into(),
+            RedisValue::SimpleStringStatic("shard"), RedisValue::Integer(shard as i64),
+        ],
+        DecodedKey::Type(key) => vec![
+            RedisValue::SimpleStringStatic("type"),
+            RedisValue::SimpleStringStatic("key"), key.into(),
+        ],
+        DecodedKey::Meta(name) => vec![
+            RedisValue::SimpleStringStatic("meta"),
+            RedisValue::SimpleStringStatic("name"), name.into(),
+        ],
+    };
+    ret.extend(parts);
+    Ok(ret.into())
+}
@@ -1,7 +1,16 @@
-use crate::commands::errors::AsyncResult;
+use std::collections::HashSet;
+use crate::{
+    commands::errors::{AsyncResult, RTError},
+    encoding::{
+        KeyEncoder, KeyDecoder, LegacyKey, DataType,
+        FORMAT_VERSION, META_FORMAT_VERSION,
+    },
+    utils::{resp_int, resp_ok, with_db},
+    client::RawClientWrapper,
+};
 use redis_module::RedisValue;
-use tikv_client::ColumnFamily;
-use super::get_client;
+use tikv_client::{ColumnFamily, Key, KvPair};
+use super::{get_client, generic::register_key_type};
 
 
 pub async fn do_async_rawkv_ascan(
@@ -43,4 +52,191 @@ pub async fn do_async_rawkv_ascan_range(
         })
         .collect();
     Ok(values.into())
-}
\ No newline at end of file
+}
+
+fn convert_legacy_key(encoder: &KeyEncoder, lkey: LegacyKey) -> (Vec<u8>, Key) {
+    match lkey {
+        LegacyKey::String(key) => {
+            let nkey = encoder.encode_string(&key);
+            (key, nkey)
+        }
+        LegacyKey::Hash(key, field) => {
+            let nkey = encoder.encode_hash(&key, &field);
+            (key, nkey)
+        }
+        LegacyKey::ListMeta(key) => {
+            let nkey = encoder.encode_list_meta_key(&key);
+            (key, nkey)
+        }
+        LegacyKey::ListElem(key, idx) => {
+            let nkey = encoder.encode_list_elem_key(&key, idx);
+            (key, nkey)
+        }
+        LegacyKey::Set(key, member) => {
+            let nkey = encoder.encode_set(&key, &member);
+            (key, nkey)
+        }
+    }
+}
+
+// Move at most `limit` keys from format version 0 layout to current layout.
+// Legacy layout has no DB, so keys are always migrated into DB 0. Keys already
+// written by current layout will not be overwritten. The cursor of each data
+// type is saved so keys that cannot be converted are skipped next time. The
+// format version is recorded by the call which finds no legacy key left.
+// Returns migrated key number.
+pub async fn do_async_migrate(limit: u64) -> AsyncResult<RedisValue> {
+    with_db(0, migrate_legacy_keys(limit)).await
+}
+
+async fn migrate_legacy_keys(limit: u64) -> AsyncResult<RedisValue> {
+    let client = get_client()?;
+    let encoder = KeyEncoder::new();
+    let decoder = KeyDecoder::new();
+    let mut migrated: u64 = 0;
+    let mut finished = true;
+    for tp in [DataType::String, DataType::Hash, DataType::List, DataType::Set] {
+        if migrated >= limit {
+            finished = false;
+            break;
+        }
+        let ckey = encoder.encode_migrate_cursor_key(tp);
+        let start_key = match client.get(ckey.clone()).await? {
+            Some(mut cursor) => {
+                cursor.push(0);
+                cursor.into()
+            }
+            None => encoder.encode_legacy_start(tp),
+        };
+        let range = start_key..encoder.encode_legacy_end(tp);
+        let batch = (limit - migrated) as u32;
+        let result = client.scan(range.into(), batch).await?;
+        if result.len() >= batch as usize {
+            // The range may have more keys
+            finished = false;
+        }
+        if result.is_empty() {
+            continue;
+        }
+        let mut old_keys: Vec<Key> = Vec::new();
+        let mut registered: HashSet<Vec<u8>> = HashSet::new();
+        let mut last_key: Vec<u8> = Vec::new();
+        for kv in result.into_iter() {
+            let (okey, value) = (kv.0, kv.1);
+            last_key = okey.clone().into();
+            migrated += 1;
+            // Keys can not be split exactly are kept in legacy layout, they
+            // are listed by `tikv.migrate skipped`.
+            let (ukey, nkey) = match decoder.decode_legacy_key(okey.clone()) {
+                Some(lkey) => convert_legacy_key(&encoder, lkey),
+                None => continue,
+            };
+            let ttl = client.get_ttl(okey.clone()).await?;
+            if ttl > 0 {
+                let _ = client.compare_and_swap_with_ttl(nkey, None, value, ttl as u64).await?;
+            } else {
+                let _ = client.compare_and_swap(nkey, None, value).await?;
+            }
+            // Legacy format allows one key holds many data types, the first
+            // migrated one will be registered.
+            if registered.insert(ukey.clone()) {
+                match register_key_type(&client, &ukey, tp).await {
+                    Err(RTError::WrongType) => {}
+                    other => other?,
+                }
+            }
+            old_keys.push(okey);
+        }
+        // Old keys are deleted before the cursor is saved, so keys are never
+        // left behind the cursor if the module stops between the two writes.
+        if !old_keys.is_empty() {
+            client.batch_delete(old_keys).await?;
+        }
+        client.put(ckey, last_key).await?;
+    }
+    if finished {
+        let vkey = encoder.encode_meta_key(META_FORMAT_VERSION);
+        client.put(vkey, vec![FORMAT_VERSION]).await?;
+    }
+    Ok(resp_int(migrated as i64))
+}
+
+// List at most `limit` raw keys left in legacy layout. After migration is
+// finished they are the keys skipped, such as Hash and Set keys can not be
+// split into key and field exactly.
+pub async fn do_async_migrate_skipped(limit: u64) -> AsyncResult<RedisValue> {
+    let client = get_client()?;
+    let encoder = KeyEncoder::new();
+    let mut keys: Vec<Vec<u8>> = Vec::new();
+    for tp in [DataType::String, DataType::Hash, DataType::List, DataType::Set] {
+        if keys.len() as u64 >= limit {
+            break;
+        }
+        let range = encoder.encode_legacy_start(tp)..encoder.encode_legacy_end(tp);
+        let result = client.scan(range.into(), (limit - keys.len() as u64) as u32).await?;
+        keys.extend(result.into_iter().map(|kv| Into::<Vec<u8>>::into(kv.0)));
+    }
+    Ok(keys.into())
+}
+
+pub async fn do_async_format_version() -> AsyncResult<RedisValue> {
+    let client = get_client()?;
+    let vkey = KeyEncoder::new().encode_meta_key(META_FORMAT_VERSION);
+    match client.get(vkey).await? {
+        Some(val) if !val.is_empty() => Ok(resp_int(val[0] as i64)),
+        _ => Ok(resp_int(0)),
+    }
+}
+
+pub async fn do_async_flushdb() -> AsyncResult<RedisValue> {
+    let client = get_client()?;
+    let encoder = KeyEncoder::new();
+    let range = encoder.encode_db_start()..encoder.encode_db_end();
+    client.delete_range(range.into()).await?;
+    Ok(resp_ok())
+}
+
+// Move all keys from one DB to another DB in batches, keys' TTL are kept.
+async fn move_db_keys(client: &RawClientWrapper, from: u32, to: u32) -> AsyncResult<()> {
+    let from_encoder = KeyEncoder::with_db(from);
+    let from_prefix: Vec<u8> = from_encoder.encode_db_start().into();
+    let to_prefix: Vec<u8> = KeyEncoder::with_db(to).encode_db_start().into();
+    loop {
+        let range = from_encoder.encode_db_start()..from_encoder.encode_db_end();
+        let result = client.scan(range.into(), 1000).await?;
+        if result.is_empty() {
+            return Ok(());
+        }
+        let mut old_keys: Vec<Key> = Vec::new();
+        let mut kvs: Vec<KvPair> = Vec::new();
+        for kv in result.into_iter() {
+            let (okey, value) = (kv.0, kv.1);
+            let okey_bytes: Vec<u8> = okey.clone().into();
+            let nkey: Key = [to_prefix.as_slice(), &okey_bytes[from_prefix.len()..]].concat().into();
+            let ttl = client.get_ttl(okey.clone()).await?;
+            if ttl > 0 {
+                let _ = client.compare_and_swap_with_ttl(nkey, None, value, ttl as u64).await?;
+            } else {
+                kvs.push(KvPair::from((nkey, value)));
+            }
+            old_keys.push(okey);
+        }
+        if !kvs.is_empty() {
+            client.batch_put(kvs).await?;
+        }
+        client.batch_delete(old_keys).await?;
+    }
+}
+
+// Swap two DBs by moving keys, DB `u32::MAX` is used as temporary space.
+pub async fn do_async_swapdb(db1: u32, db2: u32) -> AsyncResult<RedisValue> {
+    let client = get_client()?;
+    if db1 == db2 {
+        return Ok(resp_ok());
+    }
+    let tmp_db = u32::MAX;
+    move_db_keys(&client, db1, tmp_db).await?;
+    move_db_keys(&client, db2, db1).await?;
+    move_db_keys(&client, tmp_db, db2).await?;
+    Ok(resp_ok())
+}
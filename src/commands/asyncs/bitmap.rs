@@ -0,0 +1,325 @@
+use redis_module::RedisValue;
+use crate::{
+    client::RawClientWrapper,
+    commands::errors::{AsyncResult, RTError},
+    encoding::{KeyEncoder, ValueEncoder, ValueDecoder, ValueType, DataType, ChunkManifest, CHUNK_SIZE},
+    utils::{resp_int, sleep},
+};
+use super::{
+    get_client,
+    generic::{check_key_type, register_key_type, remove_key_type, reset_key_type},
+    string::{
+        cas_string_value, get_string_expire_at, get_string_value, load_string_data,
+        put_string_data, read_string_range, string_value_len, write_string_chunks,
+        delete_string_chunks, deleted_string_value, remove_deleted_value, expire_string_chunk,
+    },
+};
+
+// Bytes read in one request by BITCOUNT and BITPOS
+const BITMAP_READ_SIZE: u64 = 16 * CHUNK_SIZE as u64;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum BitOp {
+    And,
+    Or,
+    Xor,
+    Not,
+}
+
+fn get_bit(data: &[u8], offset: u64) -> bool {
+    match data.get((offset / 8) as usize) {
+        Some(b) => b & (0x80 >> (offset % 8)) != 0,
+        None => false,
+    }
+}
+
+// Set bit in data, data will be extended if needed. Returns old bit.
+fn set_bit(data: &mut Vec<u8>, offset: u64, on: bool) -> bool {
+    let byte_idx = (offset / 8) as usize;
+    let mask = 0x80 >> (offset % 8);
+    if data.len() <= byte_idx {
+        data.resize(byte_idx + 1, 0);
+    }
+    let old = data[byte_idx] & mask != 0;
+    if on {
+        data[byte_idx] |= mask;
+    } else {
+        data[byte_idx] &= !mask;
+    }
+    old
+}
+
+// Resolve Redis style range into inclusive bit range, returns None if range is
+// empty. `in_bit` means start and end are bit offsets instead of byte offsets.
+pub fn resolve_bit_range(len: u64, start: i64, end: i64, in_bit: bool) -> Option<(u64, u64)> {
+    let total = if in_bit { len * 8 } else { len } as i64;
+    let start = if start < 0 { start + total } else { start }.max(0);
+    let end = if end < 0 { end + total } else { end }.max(0).min(total - 1);
+    if total == 0 || start > end {
+        return None;
+    }
+    if in_bit {
+        Some((start as u64, end as u64))
+    } else {
+        Some((start as u64 * 8, end as u64 * 8 + 7))
+    }
+}
+
+// Set bit in chunk of chunked value and extend the manifest length if needed.
+// The chunk is written with the value's TiKV TTL, and its TTL is set again if
+// the expire time is changed by others meanwhile. Returns None if the value is
+// replaced by others during setting.
+async fn setbit_chunked(
+    client: &RawClientWrapper,
+    key: &[u8],
+    m: ChunkManifest,
+    mut expire_at: Option<u64>,
+    offset: u64,
+    on: bool,
+) -> AsyncResult<Option<bool>> {
+    let encoder = KeyEncoder::new();
+    let vencoder = ValueEncoder::new();
+    let vdecoder = ValueDecoder::new();
+    let chunk_bits = CHUNK_SIZE as u64 * 8;
+    let ckey = encoder.encode_string_chunk(key, m.gen, offset / chunk_bits);
+    let mut old = false;
+    let mut swapped = false;
+    for i in 0..2000 {
+        let prev = client.get(ckey.clone()).await?;
+        let mut chunk = prev.clone().unwrap_or_default();
+        old = set_bit(&mut chunk, offset % chunk_bits, on);
+        if old == on {
+            swapped = true;
+            break;
+        }
+        if cas_string_value(client, ckey.clone(), prev, chunk, expire_at).await? {
+            swapped = true;
+            break;
+        }
+        sleep(std::cmp::min(i, 200)).await;
+    }
+    if !swapped {
+        return Err(RTError::StringError(String::from("Cannot swapped")));
+    }
+
+    let ekey = encoder.encode_string(key);
+    let len = offset / 8 + 1;
+    for i in 0..2000 {
+        let prev = match client.get(ekey.clone()).await? {
+            Some(val) => val,
+            None => return Ok(None),
+        };
+        let decoded = vdecoder.decode(prev.clone())?;
+        let cur = match decoded.manifest() {
+            Some(cur) if cur.gen == m.gen && !decoded.is_expired() => cur,
+            _ => return Ok(None),
+        };
+        // Chunked value always keeps its expire time in header
+        if decoded.expire_at != expire_at {
+            let chunk = client.get(ckey.clone()).await?;
+            expire_string_chunk(client, ckey.clone(), chunk, decoded.expire_at).await?;
+            expire_at = decoded.expire_at;
+        }
+        if cur.len >= len {
+            return Ok(Some(old));
+        }
+        let new_m = ChunkManifest { len, gen: cur.gen };
+        let new_val = vencoder.encode_with_flags(ValueType::Chunked, 0, expire_at, &new_m.encode());
+        if cas_string_value(client, ekey.clone(), Some(prev), new_val, expire_at).await? {
+            return Ok(Some(old));
+        }
+        sleep(std::cmp::min(i, 200)).await;
+    }
+    Err(RTError::StringError(String::from("Cannot swapped")))
+}
+
+// Set bit of string value. Value not larger than `CHUNK_SIZE` is updated in
+// place, larger value is converted into chunked value so later SETBIT only
+// rewrites one chunk.
+pub async fn do_async_setbit(key: &[u8], offset: u64, on: bool) -> AsyncResult<RedisValue> {
+    let client = get_client()?;
+    check_key_type(&client, key, DataType::String).await?;
+    let encoder = KeyEncoder::new();
+    let vencoder = ValueEncoder::new();
+    let vdecoder = ValueDecoder::new();
+    let ekey = encoder.encode_string(key);
+    for i in 0..2000 {
+        let prev = client.get(ekey.clone()).await?;
+        let decoded = prev
+            .clone()
+            .map(|v| vdecoder.decode(v))
+            .transpose()?
+            .filter(|v| !v.is_expired());
+        let expire_at = match &decoded {
+            Some(v) => get_string_expire_at(&client, ekey.clone(), v.expire_at).await?,
+            None => None,
+        };
+        if let Some(m) = decoded.as_ref().and_then(|v| v.manifest()) {
+            if let Some(old) = setbit_chunked(&client, key, m, expire_at, offset, on).await? {
+                register_key_type(&client, key, DataType::String).await?;
+                return Ok(resp_int(old as i64));
+            }
+            continue;
+        }
+        let mut data = decoded.map(|v| v.data).unwrap_or_default();
+        let old = get_bit(&data, offset);
+        let len = (data.len() as u64).max(offset / 8 + 1);
+        if len <= CHUNK_SIZE as u64 {
+            set_bit(&mut data, offset, on);
+            let new_val = vencoder.encode_compressible_with_expire(&data, expire_at);
+            if cas_string_value(&client, ekey.clone(), prev, new_val, expire_at).await? {
+                register_key_type(&client, key, DataType::String).await?;
+                return Ok(resp_int(old as i64));
+            }
+        } else {
+            // Only current data is written into chunks, the bit will be set
+            // by chunked SETBIT after manifest is swapped.
+            let m = write_string_chunks(&client, key, &data, len, expire_at).await?;
+            let new_val = vencoder.encode_with_flags(ValueType::Chunked, 0, expire_at, &m.encode());
+            if cas_string_value(&client, ekey.clone(), prev, new_val, expire_at).await? {
+                continue;
+            }
+            delete_string_chunks(&client, key, m).await?;
+        }
+        sleep(std::cmp::min(i, 200)).await;
+    }
+    Err(RTError::StringError(String::from("Cannot swapped")))
+}
+
+pub async fn do_async_getbit(key: &[u8], offset: u64) -> AsyncResult<RedisValue> {
+    let client = get_client()?;
+    let decoded = match get_string_value(&client, key).await? {
+        Some((_, decoded)) => decoded,
+        None => return Ok(resp_int(0)),
+    };
+    let byte_idx = offset / 8;
+    let data = read_string_range(&client, key, &decoded, byte_idx, byte_idx + 1).await?;
+    Ok(resp_int(get_bit(&data, offset % 8) as i64))
+}
+
+// Count set bits in range, `range` is (start, end, in_bit)
+pub async fn do_async_bitcount(key: &[u8], range: Option<(i64, i64, bool)>) -> AsyncResult<RedisValue> {
+    let client = get_client()?;
+    let decoded = match get_string_value(&client, key).await? {
+        Some((_, decoded)) => decoded,
+        None => return Ok(resp_int(0)),
+    };
+    let len = string_value_len(&decoded);
+    let (start, end, in_bit) = range.unwrap_or((0, -1, false));
+    let (sbit, ebit) = match resolve_bit_range(len, start, end, in_bit) {
+        Some(r) => r,
+        None => return Ok(resp_int(0)),
+    };
+    let mut count: u64 = 0;
+    let mut pos = sbit / 8;
+    while pos <= ebit / 8 {
+        let to = (pos + BITMAP_READ_SIZE).min(ebit / 8 + 1);
+        let data = read_string_range(&client, key, &decoded, pos, to).await?;
+        for (i, b) in data.iter().enumerate() {
+            let byte_start = (pos + i as u64) * 8;
+            let mut byte = *b;
+            // Mask out bits out of range in the first and last bytes
+            if byte_start < sbit {
+                byte &= 0xFF >> (sbit - byte_start);
+            }
+            if byte_start + 7 > ebit {
+                byte &= 0xFF << (byte_start + 7 - ebit);
+            }
+            count += byte.count_ones() as u64;
+        }
+        pos = to;
+    }
+    Ok(resp_int(count as i64))
+}
+
+// Find first bit set to `bit` in range, `start` and `end` are optional and
+// `in_bit` means they are bit offsets.
+pub async fn do_async_bitpos(
+    key: &[u8],
+    bit: bool,
+    start: Option<i64>,
+    end: Option<i64>,
+    in_bit: bool,
+) -> AsyncResult<RedisValue> {
+    let client = get_client()?;
+    let decoded = match get_string_value(&client, key).await? {
+        Some((_, decoded)) => decoded,
+        None => return Ok(resp_int(if bit { -1 } else { 0 })),
+    };
+    let len = string_value_len(&decoded);
+    let (sbit, ebit) = match resolve_bit_range(len, start.unwrap_or(0), end.unwrap_or(-1), in_bit) {
+        Some(r) => r,
+        None => return Ok(resp_int(-1)),
+    };
+    let skip_byte: u8 = if bit { 0x00 } else { 0xFF };
+    let mut pos = sbit / 8;
+    while pos <= ebit / 8 {
+        let to = (pos + BITMAP_READ_SIZE).min(ebit / 8 + 1);
+        let data = read_string_range(&client, key, &decoded, pos, to).await?;
+        for (i, b) in data.iter().enumerate() {
+            let byte_start = (pos + i as u64) * 8;
+            if *b == skip_byte && byte_start >= sbit && byte_start + 7 <= ebit {
+                continue;
+            }
+            for j in 0..8 {
+                let offset = byte_start + j;
+                if offset < sbit || offset > ebit {
+                    continue;
+                }
+                if (b & (0x80 >> j) != 0) == bit {
+                    return Ok(resp_int(offset as i64));
+                }
+            }
+        }
+        pos = to;
+    }
+    // Clear bit is treated as found right after the value if end not given
+    if !bit && end.is_none() {
+        return Ok(resp_int(ebit as i64 + 1));
+    }
+    Ok(resp_int(-1))
+}
+
+// Perform bitwise operation between source keys and store result in dest key.
+// Returns length of result.
+pub async fn do_async_bitop(op: BitOp, dest: &[u8], srcs: Vec<Vec<u8>>) -> AsyncResult<RedisValue> {
+    let client = get_client()?;
+    let mut values: Vec<Vec<u8>> = Vec::new();
+    for src in srcs.iter() {
+        let data = match get_string_value(&client, src).await? {
+            Some((_, decoded)) => load_string_data(&client, src, decoded).await?,
+            None => Vec::new(),
+        };
+        values.push(data);
+    }
+    let len = values.iter().map(|v| v.len()).max().unwrap_or(0);
+    let mut result: Vec<u8> = vec![0; len];
+    for (idx, byte) in result.iter_mut().enumerate() {
+        let mut bytes = values.iter().map(|v| v.get(idx).copied().unwrap_or(0));
+        let first = bytes.next().unwrap_or(0);
+        *byte = match op {
+            BitOp::And => bytes.fold(first, |acc, b| acc & b),
+            BitOp::Or => bytes.fold(first, |acc, b| acc | b),
+            BitOp::Xor => bytes.fold(first, |acc, b| acc ^ b),
+            BitOp::Not => !first,
+        };
+    }
+
+    if result.is_empty() {
+        // Empty result deletes dest key
+        let ekey = KeyEncoder::new().encode_string(dest);
+        if let Some((prev, decoded)) = get_string_value(&client, dest).await? {
+            if cas_string_value(&client, ekey.clone(), Some(prev), deleted_string_value(), None).await? {
+                remove_deleted_value(&client, ekey).await?;
+                if let Some(m) = decoded.manifest() {
+                    delete_string_chunks(&client, dest, m).await?;
+                }
+                remove_key_type(&client, dest, DataType::String).await?;
+            }
+        }
+        return Ok(resp_int(0));
+    }
+    put_string_data(&client, dest, &result, None).await?;
+    reset_key_type(&client, dest, DataType::String).await?;
+    Ok(resp_int(len as i64))
+}
@@ -0,0 +1,89 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use redis_module::RedisValue;
+use crate::{
+    client::RawClientWrapper,
+    commands::{
+        errors::{AsyncResult, RTError},
+        get_counter_shards, MAX_COUNTER_SHARDS,
+    },
+    encoding::{KeyEncoder, ValueEncoder, ValueDecoder, ValueType, DataType},
+    utils::{resp_int, resp_ok, sleep, parse_redis_int},
+};
+use super::{
+    get_client,
+    generic::{check_key_type, register_key_type},
+};
+
+// Round robin start shard of increments, so concurrent increments of one
+// counter go to different shards.
+static SHARD_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+fn decode_shard(value: &[u8]) -> AsyncResult<i64> {
+    let data = ValueDecoder::new().decode(value.to_vec())?.data;
+    parse_redis_int(&data).ok_or(RTError::NotInteger)
+}
+
+// Sum all shards of counter. Shards are scanned by range, so shards written
+// with larger `countershards` setting are counted too.
+async fn sum_counter(client: &RawClientWrapper, key: &[u8]) -> AsyncResult<i64> {
+    let encoder = KeyEncoder::new();
+    let range = encoder.encode_counter_start(key)..encoder.encode_counter_end(key);
+    let result = client.scan(range.into(), MAX_COUNTER_SHARDS).await?;
+    let mut sum: i64 = 0;
+    for kv in result.into_iter() {
+        sum = sum.checked_add(decode_shard(&kv.1)?).ok_or(RTError::Overflow)?;
+    }
+    Ok(sum)
+}
+
+// Add step to one shard of counter and returns the counter's value as INCR
+// does, all shards are scanned for the sum and it may contain other concurrent
+// increments. With `no_sum` the scan is skipped and OK is returned. If the
+// shard is changed by others, next shard is tried instead of waiting, so
+// increments only wait when all shards are busy.
+pub async fn do_async_counter_incr(key: &[u8], step: i64, no_sum: bool) -> AsyncResult<RedisValue> {
+    let client = get_client()?;
+    check_key_type(&client, key, DataType::Counter).await?;
+    let encoder = KeyEncoder::new();
+    let vencoder = ValueEncoder::new();
+    let shards = get_counter_shards() as u64;
+    let start = SHARD_COUNTER.fetch_add(1, Ordering::Relaxed);
+    let mut swapped = false;
+    for i in 0..2000 {
+        let shard = ((start + i as u64) % shards) as u32;
+        let skey = encoder.encode_counter_shard(key, shard);
+        let prev = client.get(skey.clone()).await?;
+        let prev_int = match &prev {
+            Some(val) => decode_shard(val)?,
+            None => 0,
+        };
+        let new_int = prev_int.checked_add(step).ok_or(RTError::Overflow)?;
+        let new_val = vencoder.encode_with_flags(
+            ValueType::Integer, 0, None, new_int.to_string().as_bytes(),
+        );
+        let (_, ret) = client.compare_and_swap(skey, prev, new_val).await?;
+        if ret {
+            swapped = true;
+            break;
+        }
+        if i as u64 >= shards {
+            sleep(std::cmp::min(i, 200)).await;
+        }
+    }
+    if !swapped {
+        return Err(RTError::StringError(String::from("Cannot swapped")));
+    }
+    register_key_type(&client, key, DataType::Counter).await?;
+    if no_sum {
+        Ok(resp_ok())
+    } else {
+        Ok(resp_int(sum_counter(&client, key).await?))
+    }
+}
+
+// Returns 0 if counter not exists
+pub async fn do_async_counter_get(key: &[u8]) -> AsyncResult<RedisValue> {
+    let client = get_client()?;
+    check_key_type(&client, key, DataType::Counter).await?;
+    Ok(resp_int(sum_counter(&client, key).await?))
+}
@@ -0,0 +1,282 @@
+use std::collections::HashSet;
+use super::{
+    get_client,
+    hash::{claim_hash_data, hash_exists},
+    string::delete_string_value,
+};
+use crate::{
+    client::RawClientWrapper,
+    commands::errors::{AsyncResult, RTError},
+    encoding::{KeyEncoder, KeyDecoder, ValueDecoder, DataType},
+    utils::{resp_sstr, sleep},
+};
+use redis_module::RedisValue;
+use tikv_client::{Key, KvPair};
+
+fn type_name(tp: Option<DataType>) -> &'static str {
+    match tp {
+        Some(DataType::String) => "string",
+        Some(DataType::Hash) => "hash",
+        Some(DataType::List) => "list",
+        Some(DataType::Set) => "set",
+        Some(DataType::Counter) => "counter",
+        None => "none",
+    }
+}
+
+// Get key's data type recorded in type registry
+async fn get_registered_type(client: &RawClientWrapper, key: &[u8]) -> AsyncResult<Option<DataType>> {
+    let tkey = KeyEncoder::new().encode_type_key(key);
+    match client.get(tkey).await? {
+        Some(val) => Ok(KeyDecoder::new().decode_type(&val)),
+        None => Ok(None),
+    }
+}
+
+// String keys are not recorded in type registry, as string values can be
+// expired or removed by TiKV TTL and a permanent record would be left behind.
+// A key without record is a string if its value is alive. Records of string
+// written by earlier versions are treated the same way.
+async fn string_value_alive(client: &RawClientWrapper, key: &[u8]) -> AsyncResult<bool> {
+    let ekey = KeyEncoder::new().encode_string(key);
+    match client.get(ekey).await? {
+        // Corrupt value is kept alive, so it can only be overwritten by SET
+        Some(val) => Ok(ValueDecoder::new().decode(val).map_or(true, |v| !v.is_expired())),
+        None => Ok(false),
+    }
+}
+
+// Get key's data type from type registry, or string if key has no record and
+// its string value is alive. Hash whose fields are all expired is deleted and
+// returns None.
+pub async fn get_key_type(client: &RawClientWrapper, key: &[u8]) -> AsyncResult<Option<DataType>> {
+    match get_registered_type(client, key).await? {
+        Some(DataType::String) | None => match string_value_alive(client, key).await? {
+            true => Ok(Some(DataType::String)),
+            false => Ok(None),
+        },
+        Some(DataType::Hash) if !hash_exists(client, key).await? => Ok(None),
+        tp => Ok(tp),
+    }
+}
+
+// Check key's data type, returns WRONGTYPE if key holds other type. Hash whose
+// fields are all expired is deleted and does not hold the key. Writers of other
+// types should call `register_key_type` after data is written.
+pub async fn check_key_type(
+    client: &RawClientWrapper,
+    key: &[u8],
+    tp: DataType,
+) -> AsyncResult<()> {
+    match get_registered_type(client, key).await? {
+        Some(current) if current == tp => Ok(()),
+        Some(DataType::String) | None if tp == DataType::String => Ok(()),
+        Some(DataType::String) | None => match string_value_alive(client, key).await? {
+            true => Err(RTError::WrongType),
+            false => Ok(()),
+        },
+        Some(DataType::Hash) if !hash_exists(client, key).await? => Ok(()),
+        Some(_) => Err(RTError::WrongType),
+    }
+}
+
+// Register key as given data type after its data is written. String is not
+// registered, it is only checked that no other type is registered. Returns
+// WRONGTYPE if key is registered as other type or holds a live string value
+// written by a concurrent writer.
+pub async fn register_key_type(
+    client: &RawClientWrapper,
+    key: &[u8],
+    tp: DataType,
+) -> AsyncResult<()> {
+    let encoder = KeyEncoder::new();
+    let decoder = KeyDecoder::new();
+    let tkey = encoder.encode_type_key(key);
+    let flag = vec![encoder.get_type_flag(tp)];
+    for i in 0..2000 {
+        let prev = client.get(tkey.clone()).await?;
+        match prev.as_ref().and_then(|v| decoder.decode_type(v)) {
+            Some(current) if current == tp => return Ok(()),
+            Some(DataType::String) | None if tp == DataType::String => return Ok(()),
+            Some(DataType::String) | None if string_value_alive(client, key).await? => {
+                return Err(RTError::WrongType);
+            }
+            Some(DataType::String) | None => {}
+            Some(_) => return Err(RTError::WrongType),
+        }
+        let (_, swapped) = client.compare_and_swap(tkey.clone(), prev, flag.clone()).await?;
+        if swapped {
+            return Ok(());
+        }
+        sleep(std::cmp::min(i, 200)).await;
+    }
+    Err(RTError::StringError(String::from("Cannot swapped")))
+}
+
+// Remove key from type registry if it is registered as given data type
+pub async fn remove_key_type(
+    client: &RawClientWrapper,
+    key: &[u8],
+    tp: DataType,
+) -> AsyncResult<()> {
+    let encoder = KeyEncoder::new();
+    let tkey = encoder.encode_type_key(key);
+    if let Some(val) = client.get(tkey.clone()).await? {
+        if val == vec![encoder.get_type_flag(tp)] {
+            client.batch_delete(vec![tkey]).await?;
+        }
+    }
+    Ok(())
+}
+
+// Delete all data of a key that stored as given data type
+pub async fn purge_key_data(
+    client: &RawClientWrapper,
+    key: &[u8],
+    tp: DataType,
+) -> AsyncResult<()> {
+    let encoder = KeyEncoder::new();
+    match tp {
+        DataType::String => {
+            client.batch_delete(vec![encoder.encode_string(key)]).await?;
+            let range = encoder.encode_string_chunk_start(key)..encoder.encode_string_chunk_end(key);
+            client.delete_range(range.into()).await?;
+        }
+        DataType::Hash => {
+            client.batch_delete(vec![encoder.encode_hash_meta_key(key)]).await?;
+            let range = encoder.encode_hash_start(key)..encoder.encode_hash_end(key);
+            client.delete_range(range.into()).await?;
+        }
+        DataType::List => {
+            client.batch_delete(vec![encoder.encode_list_meta_key(key)]).await?;
+            let range = encoder.encode_list_elem_start(key)..encoder.encode_list_elem_end(key);
+            client.delete_range(range.into()).await?;
+        }
+        DataType::Set => {
+            let range = encoder.encode_set_start(key)..encoder.encode_set_end(key);
+            client.delete_range(range.into()).await?;
+        }
+        DataType::Counter => {
+            let range = encoder.encode_counter_start(key)..encoder.encode_counter_end(key);
+            client.delete_range(range.into()).await?;
+        }
+    }
+    Ok(())
+}
+
+// Claim data of a key before it is deleted, returns true if key holds any
+// data. String value and hash, list metadata are swapped by compare and swap,
+// so only one of concurrent deletes gets true. Set and counter have no
+// metadata, their data is checked by scan.
+pub async fn claim_key_data(
+    client: &RawClientWrapper,
+    key: &[u8],
+    tp: DataType,
+) -> AsyncResult<bool> {
+    let encoder = KeyEncoder::new();
+    match tp {
+        DataType::String => delete_string_value(client, key).await,
+        DataType::Hash => claim_hash_data(client, key).await,
+        DataType::List => {
+            let decoder = KeyDecoder::new();
+            let mkey = encoder.encode_list_meta_key(key);
+            for i in 0..2000 {
+                let mval = client.get(mkey.clone()).await?;
+                let (l, r) = decoder.decode_list_meta(mval.clone());
+                if r <= l {
+                    return Ok(false);
+                }
+                let (_, swapped) = client.compare_and_swap(mkey.clone(), mval, encoder.encode_list_meta(l, l)).await?;
+                if swapped {
+                    return Ok(true);
+                }
+                sleep(std::cmp::min(i, 200)).await;
+            }
+            Err(RTError::StringError(String::from("Cannot swapped")))
+        }
+        DataType::Set => {
+            let range = encoder.encode_set_start(key)..encoder.encode_set_end(key);
+            Ok(!client.scan(range.into(), 1).await?.is_empty())
+        }
+        DataType::Counter => {
+            let range = encoder.encode_counter_start(key)..encoder.encode_counter_end(key);
+            Ok(!client.scan(range.into(), 1).await?.is_empty())
+        }
+    }
+}
+
+// Make key hold given data type, data of other type will be deleted. Used by
+// commands which overwrite key whatever type it holds, such as SET, after the
+// new data is written. String keys have no record, so the record is removed.
+pub async fn reset_key_type(
+    client: &RawClientWrapper,
+    key: &[u8],
+    tp: DataType,
+) -> AsyncResult<()> {
+    let encoder = KeyEncoder::new();
+    let decoder = KeyDecoder::new();
+    let tkey = encoder.encode_type_key(key);
+    let flag = vec![encoder.get_type_flag(tp)];
+    let prev = client.get(tkey.clone()).await?;
+    if let Some(prev_tp) = prev.as_ref().and_then(|v| decoder.decode_type(v)) {
+        if prev_tp != tp {
+            purge_key_data(client, key, prev_tp).await?;
+        }
+    }
+    match prev {
+        Some(_) if tp == DataType::String => client.batch_delete(vec![tkey]).await?,
+        Some(val) if val == flag => {}
+        _ if tp == DataType::String => {}
+        _ => client.put(tkey, flag).await?,
+    }
+    Ok(())
+}
+
+// Batch version of `reset_key_type`
+pub async fn reset_keys_type(
+    client: &RawClientWrapper,
+    keys: &[Vec<u8>],
+    tp: DataType,
+) -> AsyncResult<()> {
+    let encoder = KeyEncoder::new();
+    let decoder = KeyDecoder::new();
+    let flag = vec![encoder.get_type_flag(tp)];
+    let tkeys: Vec<Key> = keys.iter().map(|k| encoder.encode_type_key(k)).collect();
+    let result = client.batch_get(tkeys.clone()).await?;
+    let mut recorded: HashSet<Key> = HashSet::new();
+    let mut registered: HashSet<Key> = HashSet::new();
+    for kv in result.into_iter() {
+        recorded.insert(kv.0.clone());
+        if kv.1 == flag {
+            registered.insert(kv.0);
+            continue;
+        }
+        if let Some(prev_tp) = decoder.decode_type(&kv.1) {
+            if let Some(pos) = tkeys.iter().position(|k| *k == kv.0) {
+                purge_key_data(client, &keys[pos], prev_tp).await?;
+            }
+        }
+    }
+    if tp == DataType::String {
+        let dkeys: Vec<Key> = tkeys.into_iter().filter(|k| recorded.contains(k)).collect();
+        if !dkeys.is_empty() {
+            client.batch_delete(dkeys).await?;
+        }
+        return Ok(());
+    }
+    let kvs: Vec<KvPair> = tkeys
+        .into_iter()
+        .filter(|k| !registered.contains(k))
+        .map(|k| KvPair::from((k, flag.clone())))
+        .collect();
+    if !kvs.is_empty() {
+        client.batch_put(kvs).await?;
+    }
+    Ok(())
+}
+
+pub async fn do_async_type(key: &[u8]) -> AsyncResult<RedisValue> {
+    let client = get_client()?;
+    let tp = get_key_type(&client, key).await?;
+    Ok(resp_sstr(type_name(tp)))
+}
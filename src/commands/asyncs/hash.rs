@@ -1,102 +1,955 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use redis_module::RedisValue;
 use tikv_client::{Key, Value};
 use crate::{
-    encoding::{KeyEncoder, KeyDecoder}, commands::errors::AsyncResult,
-    utils::resp_int,
+    encoding::{
+        KeyEncoder, KeyDecoder, DataType, ValueEncoder, ValueDecoder, ValueType, DecodedValue,
+        now_millis,
+    },
+    commands::errors::{AsyncResult, RTError},
+    utils::{
+        resp_int, resp_ok, sleep, glob_match, random_u64, parse_redis_int, parse_redis_float,
+        format_redis_float,
+    },
+    client::RawClientWrapper,
 };
-use super::get_client;
+use super::{
+    get_client,
+    generic::{check_key_type, register_key_type, remove_key_type},
+    string::{
+        cas_string_value, deleted_string_value, remove_deleted_value, get_string_expire_at,
+        ExpireCondition,
+    },
+};
+
+// Hash metadata: `[LEN(8Byte)][VOLATILE(8Byte)]`. `LEN` is the number of
+// fields without expire time, `VOLATILE` is the number of fields given an
+// expire time and not yet deleted or persisted. Fields expired by TTL can not
+// be tracked, so `VOLATILE` may be larger than the actual number and hash
+// length is counted by scan while it is not 0.
+#[derive(Clone, Copy, Default)]
+struct HashMeta {
+    len: i64,
+    volatile: i64,
+}
+
+impl HashMeta {
+    fn encode(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(16);
+        data.extend_from_slice(&self.len.to_be_bytes());
+        data.extend_from_slice(&self.volatile.to_be_bytes());
+        ValueEncoder::new().encode_with_flags(ValueType::Raw, 0, None, &data)
+    }
+
+    // Expired metadata is a deleted hash, metadata written before field TTL
+    // support is an integer of field count.
+    fn decode(value: Vec<u8>) -> AsyncResult<Self> {
+        let decoded = ValueDecoder::new().decode(value)?;
+        if decoded.is_expired() {
+            return Ok(HashMeta::default());
+        }
+        if decoded.tp == ValueType::Integer {
+            let len = parse_redis_int(&decoded.data).unwrap_or(0);
+            return Ok(HashMeta { len, volatile: 0 });
+        }
+        if decoded.data.len() != 16 {
+            return Ok(HashMeta::default());
+        }
+        Ok(HashMeta {
+            len: i64::from_be_bytes(decoded.data[..8].try_into().unwrap()),
+            volatile: i64::from_be_bytes(decoded.data[8..].try_into().unwrap()),
+        })
+    }
+
+    fn is_empty(&self) -> bool {
+        self.len <= 0 && self.volatile <= 0
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum FieldState {
+    Missing,
+    Persistent,
+    Volatile,
+}
+
+impl FieldState {
+    fn of(value: Option<&DecodedValue>) -> Self {
+        match value {
+            None => FieldState::Missing,
+            Some(v) if v.expire_at.is_some() => FieldState::Volatile,
+            Some(_) => FieldState::Persistent,
+        }
+    }
+
+    // Contribution to hash metadata's `LEN` and `VOLATILE`
+    fn meta(&self) -> HashMeta {
+        match self {
+            FieldState::Missing => HashMeta { len: 0, volatile: 0 },
+            FieldState::Persistent => HashMeta { len: 1, volatile: 0 },
+            FieldState::Volatile => HashMeta { len: 0, volatile: 1 },
+        }
+    }
+}
+
+enum FieldWrite {
+    Keep,
+    Put(Vec<u8>, Option<u64>),
+    Delete,
+}
+
+// Result of a field update, `data` is the written data
+struct FieldUpdate {
+    prev: FieldState,
+    next: FieldState,
+    data: Option<Vec<u8>>,
+}
+
+impl FieldUpdate {
+    fn add_to(&self, delta: &mut HashMeta) {
+        let (prev, next) = (self.prev.meta(), self.next.meta());
+        delta.len += next.len - prev.len;
+        delta.volatile += next.volatile - prev.volatile;
+    }
+}
+
+fn decode_live_field(value: Option<Vec<u8>>) -> AsyncResult<Option<DecodedValue>> {
+    Ok(value
+        .map(|v| ValueDecoder::new().decode(v))
+        .transpose()?
+        .filter(|v| !v.is_expired()))
+}
+
+// Max entries of one TiKV scan request
+pub const HASH_SCAN_LIMIT: u32 = 10200;
+
+// Scan one page of hash's data range from start field, expired fields are
+// skipped. Returns live fields with values and the start field of next page,
+// which is None if the range is finished.
+async fn scan_hash_page(
+    client: &RawClientWrapper,
+    key: &[u8],
+    start: Option<&[u8]>,
+    limit: u32,
+) -> AsyncResult<(Vec<(Vec<u8>, DecodedValue)>, Option<Vec<u8>>)> {
+    let encoder = KeyEncoder::new();
+    let decoder = KeyDecoder::new();
+    let start_key = match start {
+        Some(field) => encoder.encode_hash(key, field),
+        None => encoder.encode_hash_start(key),
+    };
+    let range = start_key..encoder.encode_hash_end(key);
+    let result = client.scan(range.into(), limit).await?;
+    let num_items = result.len();
+    let mut fvs: Vec<(Vec<u8>, DecodedValue)> = Vec::new();
+    let mut last_field: Vec<u8> = Vec::new();
+    for kv in result.into_iter() {
+        last_field = decoder.decode_hash_field(kv.0);
+        if let Some(decoded) = decode_live_field(Some(kv.1))? {
+            fvs.push((last_field.clone(), decoded));
+        }
+    }
+    if num_items < limit as usize {
+        return Ok((fvs, None));
+    }
+    // Smallest field after the last one
+    last_field.push(0);
+    Ok((fvs, Some(last_field)))
+}
+
+// Page through hash's whole data range, so hashes larger than one TiKV scan
+// request are returned completely.
+async fn scan_hash_all(client: &RawClientWrapper, key: &[u8]) -> AsyncResult<Vec<(Vec<u8>, DecodedValue)>> {
+    let mut ret: Vec<(Vec<u8>, DecodedValue)> = Vec::new();
+    let mut start: Option<Vec<u8>> = None;
+    loop {
+        let (fvs, next) = scan_hash_page(client, key, start.as_deref(), HASH_SCAN_LIMIT).await?;
+        ret.extend(fvs);
+        match next {
+            Some(field) => start = Some(field),
+            None => return Ok(ret),
+        }
+    }
+}
+
+// Count live fields by scan
+async fn count_hash_fields(client: &RawClientWrapper, key: &[u8]) -> AsyncResult<HashMeta> {
+    let mut meta = HashMeta::default();
+    for (_, decoded) in scan_hash_all(client, key).await?.iter() {
+        let state = FieldState::of(Some(decoded)).meta();
+        meta.len += state.len;
+        meta.volatile += state.volatile;
+    }
+    Ok(meta)
+}
+
+// Make sure hash has metadata before fields are changed. Hashes written by old
+// versions have no metadata, their fields are counted once. Missing metadata
+// of an empty hash is the same as 0, so it is not written.
+async fn ensure_hash_meta(client: &RawClientWrapper, key: &[u8]) -> AsyncResult<()> {
+    let mkey = KeyEncoder::new().encode_hash_meta_key(key);
+    if client.get(mkey.clone()).await?.is_some() {
+        return Ok(());
+    }
+    let meta = count_hash_fields(client, key).await?;
+    if meta.is_empty() {
+        return Ok(());
+    }
+    // Metadata may be created by others at the same time, it is kept
+    client.compare_and_swap(mkey, None, meta.encode()).await?;
+    Ok(())
+}
+
+// Delete hash found empty by a scan of its whole data range. Fields removed by
+// TiKV TTL do not change metadata, so a hash whose fields are all expired is
+// deleted here. `prev` is metadata read before the scan, it is only swapped if
+// not changed during the scan.
+async fn drop_empty_hash(client: &RawClientWrapper, key: &[u8], prev: Option<Vec<u8>>) -> AsyncResult<()> {
+    let meta = prev.clone().map(HashMeta::decode).transpose()?;
+    if matches!(meta, Some(m) if !m.is_empty()) {
+        swap_hash_meta(client, key, prev, HashMeta::default()).await?;
+    }
+    Ok(())
+}
+
+// Scan whole hash like `scan_hash_all`, and delete the hash if no live field
+// is found.
+async fn scan_live_hash(client: &RawClientWrapper, key: &[u8]) -> AsyncResult<Vec<(Vec<u8>, DecodedValue)>> {
+    let mkey = KeyEncoder::new().encode_hash_meta_key(key);
+    let prev = client.get(mkey).await?;
+    let fvs = scan_hash_all(client, key).await?;
+    if fvs.is_empty() {
+        drop_empty_hash(client, key, prev).await?;
+    }
+    Ok(fvs)
+}
 
-pub async fn do_async_hget(key: &str, field: &str) -> AsyncResult<RedisValue> {
+// Swap hash metadata from prev to meta. If hash has no field, metadata is
+// swapped to an expired value and hash is removed from type registry.
+async fn swap_hash_meta(
+    client: &RawClientWrapper,
+    key: &[u8],
+    prev: Option<Vec<u8>>,
+    meta: HashMeta,
+) -> AsyncResult<bool> {
+    let mkey = KeyEncoder::new().encode_hash_meta_key(key);
+    if meta.is_empty() {
+        if cas_string_value(client, mkey.clone(), prev, deleted_string_value(), None).await? {
+            remove_deleted_value(client, mkey.clone()).await?;
+            remove_key_type(client, key, DataType::Hash).await?;
+            // A concurrent HSET may recreate metadata and register the type
+            // before it is removed above. Writers update metadata before
+            // registering, so the hash is registered again if it is alive.
+            let current = client.get(mkey).await?.map(HashMeta::decode).transpose()?;
+            if matches!(current, Some(m) if !m.is_empty()) {
+                match register_key_type(client, key, DataType::Hash).await {
+                    Err(RTError::WrongType) => {}
+                    other => other?,
+                }
+            }
+            return Ok(true);
+        }
+        return Ok(false);
+    }
+    cas_string_value(client, mkey, prev, meta.encode(), None).await
+}
+
+// Add delta to hash's metadata. It is called after fields are written, TiKV's
+// RawKV API can only compare and swap one key atomically, so field and
+// metadata are two separate writes. The field is written first as the data is
+// what users asked for, if the module stops or TiKV fails between the two
+// writes, the error is returned to the client and `LEN` is left off by the
+// changed fields. It is not repaired automatically, as recounting a hash
+// while others are changing it can not be done without the same window.
+async fn update_hash_meta(client: &RawClientWrapper, key: &[u8], delta: HashMeta) -> AsyncResult<()> {
+    if delta.len == 0 && delta.volatile == 0 {
+        return Ok(());
+    }
+    let mkey = KeyEncoder::new().encode_hash_meta_key(key);
+    for i in 0..2000 {
+        let prev = client.get(mkey.clone()).await?;
+        let mut meta = prev.clone().map(HashMeta::decode).transpose()?.unwrap_or_default();
+        meta.len += delta.len;
+        meta.volatile = (meta.volatile + delta.volatile).max(0);
+        if swap_hash_meta(client, key, prev, meta).await? {
+            return Ok(());
+        }
+        sleep(std::cmp::min(i, 200)).await;
+    }
+    Err(RTError::StringError(String::from("Cannot swapped")))
+}
+
+// Update field with compare and swap, `update` gets current live value and
+// returns how to write the field. Field's TiKV TTL is set by its expire time.
+async fn cas_update_field<F>(
+    client: &RawClientWrapper,
+    key: &[u8],
+    field: &[u8],
+    update: F,
+) -> AsyncResult<FieldUpdate>
+where
+    F: Fn(Option<&DecodedValue>) -> AsyncResult<FieldWrite>,
+{
+    let ekey = KeyEncoder::new().encode_hash(key, field);
+    let vencoder = ValueEncoder::new();
+    for i in 0..2000 {
+        let prev = client.get(ekey.clone()).await?;
+        let decoded = decode_live_field(prev.clone())?;
+        let prev_state = FieldState::of(decoded.as_ref());
+        match update(decoded.as_ref())? {
+            FieldWrite::Keep => {
+                return Ok(FieldUpdate { prev: prev_state, next: prev_state, data: None });
+            }
+            FieldWrite::Delete => {
+                if cas_string_value(client, ekey.clone(), prev, deleted_string_value(), None).await? {
+                    remove_deleted_value(client, ekey).await?;
+                    return Ok(FieldUpdate { prev: prev_state, next: FieldState::Missing, data: None });
+                }
+            }
+            FieldWrite::Put(data, expire_at) => {
+                let new_val = vencoder.encode_compressible_with_expire(&data, expire_at);
+                if cas_string_value(client, ekey.clone(), prev, new_val, expire_at).await? {
+                    let next = if expire_at.is_some() { FieldState::Volatile } else { FieldState::Persistent };
+                    return Ok(FieldUpdate { prev: prev_state, next, data: Some(data) });
+                }
+            }
+        }
+        sleep(std::cmp::min(i, 200)).await;
+    }
+    Err(RTError::StringError(String::from("Cannot swapped")))
+}
+
+// Update one field of hash and its metadata
+async fn update_hash_field<F>(
+    client: &RawClientWrapper,
+    key: &[u8],
+    field: &[u8],
+    update: F,
+) -> AsyncResult<FieldUpdate>
+where
+    F: Fn(Option<&DecodedValue>) -> AsyncResult<FieldWrite>,
+{
+    check_key_type(client, key, DataType::Hash).await?;
+    ensure_hash_meta(client, key).await?;
+    let ret = cas_update_field(client, key, field, update).await?;
+    let mut delta = HashMeta::default();
+    ret.add_to(&mut delta);
+    update_hash_meta(client, key, delta).await?;
+    if ret.next != FieldState::Missing {
+        register_key_type(client, key, DataType::Hash).await?;
+    }
+    Ok(ret)
+}
+
+// Put field without expire time by compare and swap with `prev` read before,
+// the field is updated by `cas_update_field` if it is changed since then.
+async fn put_hash_field(
+    client: &RawClientWrapper,
+    key: &[u8],
+    field: &[u8],
+    prev: Option<Value>,
+    value: Vec<u8>,
+) -> AsyncResult<FieldUpdate> {
+    let ekey = KeyEncoder::new().encode_hash(key, field);
+    let prev_state = FieldState::of(decode_live_field(prev.clone())?.as_ref());
+    let new_val = ValueEncoder::new().encode_compressible_with_expire(&value, None);
+    if cas_string_value(client, ekey, prev, new_val, None).await? {
+        return Ok(FieldUpdate { prev: prev_state, next: FieldState::Persistent, data: Some(value) });
+    }
+    cas_update_field(client, key, field, |_| Ok(FieldWrite::Put(value.clone(), None))).await
+}
+
+// Set fields and returns number of created fields, expire time of fields is
+// removed as Redis does. Previous values are read by one batch get, and
+// metadata is updated once after all fields are written.
+async fn put_hash_fields(
+    client: &RawClientWrapper,
+    key: &[u8],
+    fvs: Vec<(Vec<u8>, Vec<u8>)>,
+) -> AsyncResult<i64> {
+    check_key_type(client, key, DataType::Hash).await?;
+    ensure_hash_meta(client, key).await?;
+    // The last value of a repeated field is kept as Redis does
+    let fvs: HashMap<Vec<u8>, Vec<u8>> = fvs.into_iter().collect();
+    let encoder = KeyEncoder::new();
+    let keys: Vec<Key> = fvs.keys().map(|f| encoder.encode_hash(key, f)).collect();
+    let mut prevs: HashMap<Key, Value> = client
+        .batch_get(keys)
+        .await?
+        .into_iter()
+        .map(|pair| (pair.0, pair.1))
+        .collect();
+    let mut created: i64 = 0;
+    let mut delta = HashMeta::default();
+    for (field, value) in fvs.into_iter() {
+        let prev = prevs.remove(&encoder.encode_hash(key, &field));
+        let ret = put_hash_field(client, key, &field, prev, value).await?;
+        if ret.prev == FieldState::Missing {
+            created += 1;
+        }
+        ret.add_to(&mut delta);
+    }
+    update_hash_meta(client, key, delta).await?;
+    register_key_type(client, key, DataType::Hash).await?;
+    Ok(created)
+}
+
+pub async fn do_async_hget(key: &[u8], field: &[u8]) -> AsyncResult<RedisValue> {
     let client = get_client()?;
+    check_key_type(&client, key, DataType::Hash).await?;
     let ekey = KeyEncoder::new().encode_hash(key, field);
     let value = client.get(ekey).await?;
-    Ok(value.into())
+    Ok(ValueDecoder::new().decode_opt(value)?.into())
 }
 
-pub async fn do_async_hput(
-    key: &str,
-    field: &str,
-    val: &str,
+// Returns number of created fields
+pub async fn do_async_hput(key: &[u8], fvs: Vec<(Vec<u8>, Vec<u8>)>) -> AsyncResult<RedisValue> {
+    let client = get_client()?;
+    let created = put_hash_fields(&client, key, fvs).await?;
+    Ok(resp_int(created))
+}
+
+pub async fn do_async_batch_hput(
+    key: &[u8],
+    fvs: Vec<(Vec<u8>, Vec<u8>)>,
 ) -> AsyncResult<RedisValue> {
     let client = get_client()?;
+    put_hash_fields(&client, key, fvs).await?;
+    Ok(resp_ok())
+}
+
+pub async fn do_async_hsetnx(key: &[u8], field: &[u8], value: &[u8]) -> AsyncResult<RedisValue> {
+    let client = get_client()?;
+    let ret = update_hash_field(&client, key, field, |prev| match prev {
+        Some(_) => Ok(FieldWrite::Keep),
+        None => Ok(FieldWrite::Put(value.to_vec(), None)),
+    }).await?;
+    Ok(resp_int(ret.data.is_some() as i64))
+}
+
+// Expire time of field is kept
+pub async fn do_async_hincrby(key: &[u8], field: &[u8], step: i64) -> AsyncResult<RedisValue> {
+    let client = get_client()?;
+    let ret = update_hash_field(&client, key, field, |prev| {
+        let prev_int = match prev {
+            Some(v) => parse_redis_int(&v.data).ok_or(RTError::HashNotInteger)?,
+            None => 0,
+        };
+        let new_int = prev_int.checked_add(step).ok_or(RTError::Overflow)?;
+        Ok(FieldWrite::Put(new_int.to_string().into_bytes(), prev.and_then(|v| v.expire_at)))
+    }).await?;
+    let new_int = ret.data.and_then(|data| parse_redis_int(&data)).unwrap_or(0);
+    Ok(resp_int(new_int))
+}
+
+pub async fn do_async_hincrbyfloat(key: &[u8], field: &[u8], step: f64) -> AsyncResult<RedisValue> {
+    let client = get_client()?;
+    let ret = update_hash_field(&client, key, field, |prev| {
+        let prev_float = match prev {
+            Some(v) => parse_redis_float(&v.data).ok_or(RTError::HashNotFloat)?,
+            None => 0.0,
+        };
+        let new_float = prev_float + step;
+        if !new_float.is_finite() {
+            return Err(RTError::NanOrInfinity);
+        }
+        Ok(FieldWrite::Put(format_redis_float(new_float).into_bytes(), prev.and_then(|v| v.expire_at)))
+    }).await?;
+    Ok(ret.data.unwrap_or_default().into())
+}
+
+// Hash without volatile fields returns `LEN` of metadata directly, otherwise
+// fields are counted by scan. If no volatile field is alive, `VOLATILE` is
+// reset, and hash is deleted if no field is alive at all. Metadata is only
+// swapped if it is not changed during the scan.
+async fn get_hash_len(client: &RawClientWrapper, key: &[u8]) -> AsyncResult<i64> {
+    let mkey = KeyEncoder::new().encode_hash_meta_key(key);
+    let prev = match client.get(mkey).await? {
+        Some(val) => val,
+        // Hash written by old versions has no metadata
+        None => {
+            let counted = count_hash_fields(client, key).await?;
+            return Ok(counted.len + counted.volatile);
+        }
+    };
+    let meta = HashMeta::decode(prev.clone())?;
+    if meta.volatile == 0 {
+        return Ok(meta.len.max(0));
+    }
+    let counted = count_hash_fields(client, key).await?;
+    if counted.is_empty() {
+        drop_empty_hash(client, key, Some(prev)).await?;
+    } else if counted.volatile == 0 {
+        swap_hash_meta(client, key, Some(prev), HashMeta { len: meta.len, volatile: 0 }).await?;
+    }
+    Ok(counted.len + counted.volatile)
+}
+
+// Check whether hash has any live field. Hash with volatile fields is counted
+// by `get_hash_len`, so it is deleted if all its fields are expired.
+pub async fn hash_exists(client: &RawClientWrapper, key: &[u8]) -> AsyncResult<bool> {
+    let mkey = KeyEncoder::new().encode_hash_meta_key(key);
+    match client.get(mkey).await? {
+        Some(val) => {
+            let meta = HashMeta::decode(val)?;
+            if meta.volatile > 0 {
+                Ok(get_hash_len(client, key).await? > 0)
+            } else {
+                Ok(meta.len > 0)
+            }
+        }
+        // Hash written by old versions has no metadata
+        None => Ok(true),
+    }
+}
+
+// Swap hash metadata to an expired value before hash is deleted, returns true
+// if hash has any live field. Only one of concurrent deletes can swap it.
+pub async fn claim_hash_data(client: &RawClientWrapper, key: &[u8]) -> AsyncResult<bool> {
+    let mkey = KeyEncoder::new().encode_hash_meta_key(key);
+    for i in 0..2000 {
+        let prev = client.get(mkey.clone()).await?;
+        let meta = prev.clone().map(HashMeta::decode).transpose()?;
+        let exists = match meta {
+            Some(meta) if meta.volatile == 0 => meta.len > 0,
+            _ => !count_hash_fields(client, key).await?.is_empty(),
+        };
+        if !exists {
+            return Ok(false);
+        }
+        if cas_string_value(client, mkey.clone(), prev, deleted_string_value(), None).await? {
+            remove_deleted_value(client, mkey).await?;
+            return Ok(true);
+        }
+        sleep(std::cmp::min(i, 200)).await;
+    }
+    Err(RTError::StringError(String::from("Cannot swapped")))
+}
+
+pub async fn do_async_hlen(key: &[u8]) -> AsyncResult<RedisValue> {
+    let client = get_client()?;
+    check_key_type(&client, key, DataType::Hash).await?;
+    Ok(resp_int(get_hash_len(&client, key).await?))
+}
+
+pub async fn do_async_hstrlen(key: &[u8], field: &[u8]) -> AsyncResult<RedisValue> {
+    let client = get_client()?;
+    check_key_type(&client, key, DataType::Hash).await?;
     let ekey = KeyEncoder::new().encode_hash(key, field);
-    let _ = client.put(ekey, val).await?;
-    Ok(resp_int(1))
+    let value = ValueDecoder::new().decode_opt(client.get(ekey).await?)?;
+    Ok(resp_int(value.map_or(0, |v| v.len() as i64)))
 }
 
-pub async fn do_async_hscan(key: &str) -> AsyncResult<RedisValue> {
+pub async fn do_async_hgetall(key: &[u8]) -> AsyncResult<RedisValue> {
     let client = get_client()?;
-    let encoder = KeyEncoder::new();
-    let decoder = KeyDecoder::new();
-    let range = encoder.encode_hash_start(key)..encoder.encode_hash_end(key);
-    let result = client.scan(range.into(), 10200).await?;
+    check_key_type(&client, key, DataType::Hash).await?;
     let mut values: Vec<Vec<u8>> = Vec::new();
-    let _ = result.into_iter().for_each(|p| {
-        values.push(decoder.decode_hash_field(p.key().to_owned(), key));
-        values.push(Into::<Vec<u8>>::into(p.value().to_owned()));
-    });
+    for (field, value) in scan_live_hash(&client, key).await?.into_iter() {
+        values.push(field);
+        values.push(value.data);
+    }
     Ok(values.into())
 }
 
-pub async fn do_async_batch_hget(keys: Vec<Key>) -> AsyncResult<RedisValue> {
+// Cursor is hex of the field to start from, "0" means start or end of scan
+pub fn encode_hscan_cursor(field: Option<Vec<u8>>) -> String {
+    match field {
+        Some(field) => field.iter().map(|b| format!("{:02x}", b)).collect(),
+        None => String::from("0"),
+    }
+}
+
+pub fn decode_hscan_cursor(cursor: &[u8]) -> Option<Option<Vec<u8>>> {
+    if cursor == b"0" {
+        return Some(None);
+    }
+    if cursor.is_empty() || cursor.len() % 2 != 0 {
+        return None;
+    }
+    let hex = std::str::from_utf8(cursor).ok()?;
+    let field = (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect::<Option<Vec<u8>>>()?;
+    Some(Some(field))
+}
+
+// Scan `count` entries of hash from cursor, fields not matched by pattern are
+// filtered after scan, so a page may be empty before the scan finished.
+pub async fn do_async_hscan(
+    key: &[u8],
+    cursor: Option<Vec<u8>>,
+    pattern: Option<Vec<u8>>,
+    count: u32,
+) -> AsyncResult<RedisValue> {
+    let client = get_client()?;
+    check_key_type(&client, key, DataType::Hash).await?;
+    let (fvs, next) = scan_hash_page(&client, key, cursor.as_deref(), count).await?;
+    let mut values: Vec<Vec<u8>> = Vec::new();
+    for (field, value) in fvs.into_iter() {
+        if let Some(pattern) = &pattern {
+            if !glob_match(pattern, &field) {
+                continue;
+            }
+        }
+        values.push(field);
+        values.push(value.data);
+    }
+    Ok(RedisValue::Array(vec![encode_hscan_cursor(next).into(), values.into()]))
+}
+
+pub async fn do_async_batch_hget(key: &[u8], fields: Vec<Vec<u8>>) -> AsyncResult<RedisValue> {
     let client = get_client()?;
+    check_key_type(&client, key, DataType::Hash).await?;
+    let encoder = KeyEncoder::new();
+    let keys: Vec<Key> = fields.iter().map(|f| encoder.encode_hash(key, f)).collect();
     let result = client.batch_get(keys.clone()).await?;
     let ret: HashMap<Key, Value> = result
         .into_iter()
         .map(|pair| (pair.0, pair.1))
         .collect();
-    let values: Vec<RedisValue> = keys
+    let vdecoder = ValueDecoder::new();
+    let values = keys
         .into_iter()
-        .map(|k| {
+        .map(|k| -> AsyncResult<RedisValue> {
             let data = ret.get(Into::<Key>::into(k).as_ref());
-            match data {
-                Some(val) => val.to_owned().into(),
-                None => RedisValue::Null,
-            }
+            Ok(vdecoder.decode_opt(data.cloned())?.into())
         })
-        .collect();
+        .collect::<AsyncResult<Vec<RedisValue>>>()?;
     Ok(values.into())
 }
 
-pub async fn do_async_batch_hdel(keys: Vec<Key>) -> AsyncResult<RedisValue> {
+// Fields are swapped to expired values one by one, so each deleted field is
+// counted only once even if it is deleted by others at the same time. Returns
+// number of deleted fields.
+pub async fn do_async_batch_hdel(key: &[u8], fields: Vec<Vec<u8>>) -> AsyncResult<RedisValue> {
     let client = get_client()?;
-    let num_keys = keys.len();
-    let _ = client.batch_delete(keys).await?;
-    Ok(resp_int(num_keys as i64))
+    check_key_type(&client, key, DataType::Hash).await?;
+    ensure_hash_meta(&client, key).await?;
+    let mut num_deleted: i64 = 0;
+    let mut delta = HashMeta::default();
+    for field in fields.iter() {
+        let ret = cas_update_field(&client, key, field, |prev| match prev {
+            Some(_) => Ok(FieldWrite::Delete),
+            None => Ok(FieldWrite::Keep),
+        }).await?;
+        if ret.prev != FieldState::Missing {
+            num_deleted += 1;
+        }
+        ret.add_to(&mut delta);
+    }
+    update_hash_meta(&client, key, delta).await?;
+    Ok(resp_int(num_deleted))
 }
 
-pub async fn do_async_hscan_fields(key: &str) -> AsyncResult<RedisValue> {
+pub async fn do_async_hscan_fields(key: &[u8]) -> AsyncResult<RedisValue> {
     let client = get_client()?;
-    let encoder = KeyEncoder::new();
-    let decoder = KeyDecoder::new();
-    let range = encoder.encode_hash_start(key)..encoder.encode_hash_end(key);
-    let result = client.scan(range.into(), 10200).await?;
-    let mut values: Vec<Vec<u8>> = Vec::new();
-    result.into_iter().for_each(|p| {
-        values.push(decoder.decode_hash_field(
-            p.key().to_owned(),
-            key,
-        ));
-    });
+    check_key_type(&client, key, DataType::Hash).await?;
+    let fvs = scan_live_hash(&client, key).await?;
+    let values: Vec<Vec<u8>> = fvs.into_iter().map(|(field, _)| field).collect();
     Ok(values.into())
 }
 
-pub async fn do_async_hscan_values(key: &str) -> AsyncResult<RedisValue> {
+pub async fn do_async_hscan_values(key: &[u8]) -> AsyncResult<RedisValue> {
     let client = get_client()?;
-    let encoder = KeyEncoder::new();
-    let range = encoder.encode_hash_start(key)..encoder.encode_hash_end(key);
-    let result = client.scan(range.into(), 10200).await?;
-    let mut values: Vec<Vec<u8>> = Vec::new();
-    result.into_iter().for_each(|p| {
-        values.push(Into::<Vec<u8>>::into(p.value().to_owned()));
-    });
+    check_key_type(&client, key, DataType::Hash).await?;
+    let fvs = scan_live_hash(&client, key).await?;
+    let values: Vec<Vec<u8>> = fvs.into_iter().map(|(_, value)| value.data).collect();
     Ok(values.into())
 }
 
-pub async fn do_async_hexists(key: &str, field: &str) -> AsyncResult<RedisValue> {
+pub async fn do_async_hexists(key: &[u8], field: &[u8]) -> AsyncResult<RedisValue> {
+    let client = get_client()?;
+    check_key_type(&client, key, DataType::Hash).await?;
+    let encoder = KeyEncoder::new();
+    let value = client.get(encoder.encode_hash(key, field)).await?;
+    Ok(resp_int(decode_live_field(value)?.is_some() as i64))
+}
+
+// Set expire time of fields, field is deleted if the time is passed. Returns
+// for each field: -2 if field not exists, 0 if condition not matched, 1 if
+// expire time is set and 2 if field is deleted.
+pub async fn do_async_hexpire_at(
+    key: &[u8],
+    fields: Vec<Vec<u8>>,
+    expire_at: u64,
+    cond: ExpireCondition,
+) -> AsyncResult<RedisValue> {
+    let client = get_client()?;
+    check_key_type(&client, key, DataType::Hash).await?;
+    ensure_hash_meta(&client, key).await?;
+    let mut rets: Vec<RedisValue> = Vec::new();
+    let mut delta = HashMeta::default();
+    for field in fields.iter() {
+        let ret = cas_update_field(&client, key, field, |prev| {
+            let prev = match prev {
+                Some(v) => v,
+                None => return Ok(FieldWrite::Keep),
+            };
+            if !cond.check(prev.expire_at, expire_at) {
+                return Ok(FieldWrite::Keep);
+            }
+            if expire_at <= now_millis() {
+                return Ok(FieldWrite::Delete);
+            }
+            Ok(FieldWrite::Put(prev.data.clone(), Some(expire_at)))
+        }).await?;
+        let code = if ret.prev == FieldState::Missing {
+            -2
+        } else if ret.next == FieldState::Missing {
+            2
+        } else if ret.data.is_none() {
+            0
+        } else {
+            1
+        };
+        rets.push(resp_int(code));
+        ret.add_to(&mut delta);
+    }
+    update_hash_meta(&client, key, delta).await?;
+    Ok(rets.into())
+}
+
+// Remove expire time of fields. Returns for each field: -2 if field not exists,
+// -1 if field has no expire time and 1 if expire time is removed.
+pub async fn do_async_hpersist(key: &[u8], fields: Vec<Vec<u8>>) -> AsyncResult<RedisValue> {
     let client = get_client()?;
+    check_key_type(&client, key, DataType::Hash).await?;
+    ensure_hash_meta(&client, key).await?;
+    let mut rets: Vec<RedisValue> = Vec::new();
+    let mut delta = HashMeta::default();
+    for field in fields.iter() {
+        let ret = cas_update_field(&client, key, field, |prev| match prev {
+            Some(v) if v.expire_at.is_some() => Ok(FieldWrite::Put(v.data.clone(), None)),
+            _ => Ok(FieldWrite::Keep),
+        }).await?;
+        let code = match ret.prev {
+            FieldState::Missing => -2,
+            FieldState::Persistent => -1,
+            FieldState::Volatile => 1,
+        };
+        rets.push(resp_int(code));
+        ret.add_to(&mut delta);
+    }
+    update_hash_meta(&client, key, delta).await?;
+    Ok(rets.into())
+}
+
+// Get TTL of fields in milliseconds, or in seconds if `in_ms` is false.
+// Returns -2 for field not exists and -1 for field has no expire time.
+pub async fn do_async_httl(key: &[u8], fields: Vec<Vec<u8>>, in_ms: bool) -> AsyncResult<RedisValue> {
+    let client = get_client()?;
+    check_key_type(&client, key, DataType::Hash).await?;
+    let encoder = KeyEncoder::new();
+    let mut rets: Vec<RedisValue> = Vec::new();
+    for field in fields.iter() {
+        let ekey = encoder.encode_hash(key, field);
+        let decoded = match decode_live_field(client.get(ekey.clone()).await?)? {
+            Some(v) => v,
+            None => {
+                rets.push(resp_int(-2));
+                continue;
+            }
+        };
+        let ttl_ms = match get_string_expire_at(&client, ekey, decoded.expire_at).await? {
+            Some(ts) => ts.saturating_sub(now_millis()),
+            None => {
+                rets.push(resp_int(-1));
+                continue;
+            }
+        };
+        if in_ms {
+            rets.push(resp_int(ttl_ms as i64));
+        } else {
+            rets.push(resp_int(((ttl_ms + 500) / 1000) as i64));
+        }
+    }
+    Ok(rets.into())
+}
+
+// Extra probes allowed for probes that find expired or repeated fields
+const SAMPLE_EXTRA_PROBES: usize = 8;
+
+// Smallest field not less than start, expired fields are returned too
+async fn first_field_from(client: &RawClientWrapper, key: &[u8], start: &[u8]) -> AsyncResult<Option<Vec<u8>>> {
     let encoder = KeyEncoder::new();
-    let result = client.batch_get(vec![encoder.encode_hash(key, field)]).await?;
-    Ok(resp_int(result.len() as i64))
-}
\ No newline at end of file
+    let range = encoder.encode_hash(key, start)..encoder.encode_hash_end(key);
+    let result = client.scan(range.into(), 1).await?;
+    Ok(result.into_iter().next().map(|kv| KeyDecoder::new().decode_hash_field(kv.0)))
+}
+
+// Smallest byte string larger than all strings with the prefix
+fn prefix_successor(prefix: &[u8]) -> Option<Vec<u8>> {
+    let mut ret = prefix.to_vec();
+    while let Some(b) = ret.pop() {
+        if b < 0xFF {
+            ret.push(b + 1);
+            return Some(ret);
+        }
+    }
+    None
+}
+
+// Random probe keys inside hash's field range. All fields share `prefix`, and
+// the byte after it is between `lo` and `hi`, so a probe of the prefix, a
+// random byte in the range and random tail bytes always falls between the
+// first and the last field.
+struct FieldSampler {
+    prefix: Vec<u8>,
+    lo: u8,
+    hi: u8,
+}
+
+impl FieldSampler {
+    // Build sampler with a few single key scans, returns None if hash is empty
+    async fn new(client: &RawClientWrapper, key: &[u8]) -> AsyncResult<Option<Self>> {
+        let first = match first_field_from(client, key, &[]).await? {
+            Some(field) => field,
+            None => return Ok(None),
+        };
+        // Longest prefix of first field shared by all fields
+        let (mut shared, mut unshared) = (0, first.len() + 1);
+        while shared + 1 < unshared {
+            let mid = (shared + unshared) / 2;
+            let outside = match prefix_successor(&first[..mid]) {
+                Some(next) => first_field_from(client, key, &next).await?.is_some(),
+                None => false,
+            };
+            if outside {
+                unshared = mid;
+            } else {
+                shared = mid;
+            }
+        }
+        let prefix = first[..shared].to_vec();
+        // Largest byte after prefix
+        let lo = first.get(shared).copied().unwrap_or(0);
+        let (mut hi, mut none) = (lo as u16, 0x100u16);
+        while hi + 1 < none {
+            let mid = (hi + none) / 2;
+            let mut start = prefix.clone();
+            start.push(mid as u8);
+            if first_field_from(client, key, &start).await?.is_some() {
+                hi = mid;
+            } else {
+                none = mid;
+            }
+        }
+        Ok(Some(FieldSampler { prefix, lo, hi: hi as u8 }))
+    }
+
+    fn probe(&self) -> Vec<u8> {
+        let mut ret = self.prefix.clone();
+        let span = (self.hi - self.lo) as u64 + 1;
+        ret.push(self.lo + (random_u64() % span) as u8);
+        ret.extend_from_slice(&random_u64().to_be_bytes());
+        ret
+    }
+
+    // Return the first live field from a random probe, wraps to the first
+    // field if the end of hash is reached.
+    async fn sample_one(
+        &self,
+        client: &RawClientWrapper,
+        key: &[u8],
+    ) -> AsyncResult<Option<(Vec<u8>, DecodedValue)>> {
+        let probe = self.probe();
+        let (mut fvs, next) = scan_hash_page(client, key, Some(&probe), 1).await?;
+        if next.is_none() && fvs.is_empty() {
+            let (head, _) = scan_hash_page(client, key, None, 1).await?;
+            fvs.extend(head.into_iter().filter(|(field, _)| *field < probe));
+        }
+        Ok(fvs.pop())
+    }
+}
+
+// Fisher-Yates shuffle
+fn shuffle<T>(items: &mut [T]) {
+    for i in (1..items.len()).rev() {
+        let j = (random_u64() % (i as u64 + 1)) as usize;
+        items.swap(i, j);
+    }
+}
+
+// Return random fields of hash as Redis HRANDFIELD. Without count returns one
+// field, positive count returns distinct fields and negative count allows the
+// same field multiple times. Every field is sampled by its own random probe,
+// so sampled fields are not correlated. As Redis does, if count is close to
+// hash's length, hash is scanned fully and fields are picked from all fields.
+pub async fn do_async_hrandfield(
+    key: &[u8],
+    count: Option<i64>,
+    with_values: bool,
+) -> AsyncResult<RedisValue> {
+    let client = get_client()?;
+    check_key_type(&client, key, DataType::Hash).await?;
+    let sampler = match FieldSampler::new(&client, key).await? {
+        Some(sampler) => sampler,
+        None if count.is_some() => return Ok(RedisValue::Array(vec![])),
+        None => return Ok(RedisValue::Null),
+    };
+    let count = match count {
+        Some(count) => count,
+        None => {
+            // Probe may only find expired fields, retry a few times
+            for _ in 0..SAMPLE_EXTRA_PROBES {
+                if let Some((field, _)) = sampler.sample_one(&client, key).await? {
+                    return Ok(field.into());
+                }
+            }
+            return Ok(RedisValue::Null);
+        }
+    };
+    let distinct = count > 0;
+    let wanted = count.unsigned_abs() as usize;
+    let mut fvs: Vec<(Vec<u8>, Vec<u8>)> = Vec::new();
+    // Metadata is used as the estimate of hash length without counting, it
+    // includes volatile fields which may be expired. Hash written by old
+    // versions has no metadata, it is scanned fully.
+    let mkey = KeyEncoder::new().encode_hash_meta_key(key);
+    let estimate = match client.get(mkey).await? {
+        Some(val) => {
+            let meta = HashMeta::decode(val)?;
+            meta.len.max(0).saturating_add(meta.volatile.max(0)) as usize
+        }
+        None => 0,
+    };
+    if wanted.saturating_mul(3) >= estimate {
+        let all: Vec<(Vec<u8>, Vec<u8>)> = scan_live_hash(&client, key).await?
+            .into_iter()
+            .map(|(field, value)| (field, value.data))
+            .collect();
+        if distinct {
+            fvs = all;
+        } else if !all.is_empty() {
+            for _ in 0..wanted {
+                fvs.push(all[(random_u64() % all.len() as u64) as usize].clone());
+            }
+        }
+    } else {
+        let mut seen: HashSet<Vec<u8>> = HashSet::new();
+        // Limit probes, hash length may be larger than the number of live fields
+        let mut probes = 0;
+        while fvs.len() < wanted && probes < wanted * 2 + SAMPLE_EXTRA_PROBES {
+            probes += 1;
+            let (field, value) = match sampler.sample_one(&client, key).await? {
+                Some(fv) => fv,
+                None => continue,
+            };
+            if distinct && !seen.insert(field.clone()) {
+                continue;
+            }
+            fvs.push((field, value.data));
+        }
+    }
+    shuffle(&mut fvs);
+    fvs.truncate(wanted);
+    let mut values: Vec<Vec<u8>> = Vec::new();
+    for (field, value) in fvs.into_iter() {
+        values.push(field);
+        if with_values {
+            values.push(value);
+        }
+    }
+    Ok(values.into())
+}
@@ -0,0 +1,129 @@
+use redis_module::RedisValue;
+use crate::{
+    client::RawClientWrapper,
+    commands::errors::{AsyncResult, RTError},
+    encoding::{KeyEncoder, ValueEncoder, ValueDecoder, ValueType, DataType, DecodedValue, HyperLogLog},
+    utils::{resp_int, resp_ok, sleep},
+};
+use super::{
+    get_client,
+    generic::{check_key_type, register_key_type},
+    string::{cas_string_value, get_string_expire_at, get_string_value, release_string_value},
+};
+
+// HyperLogLog is small enough to be a plain value, so chunked value is invalid
+fn decode_hll(decoded: &DecodedValue) -> AsyncResult<HyperLogLog> {
+    if decoded.tp == ValueType::Chunked {
+        return Err(RTError::InvalidHll);
+    }
+    HyperLogLog::decode(&decoded.data).ok_or(RTError::InvalidHll)
+}
+
+async fn load_hll(client: &RawClientWrapper, key: &[u8]) -> AsyncResult<Option<HyperLogLog>> {
+    match get_string_value(client, key).await? {
+        Some((_, decoded)) => Ok(Some(decode_hll(&decoded)?)),
+        None => Ok(None),
+    }
+}
+
+// Update HyperLogLog of key with compare and swap. `update` returns true if the
+// HyperLogLog is changed and should be written. Expire time of key is kept.
+// Returns true if the value is written.
+async fn cas_update_hll<F>(client: &RawClientWrapper, key: &[u8], update: F) -> AsyncResult<bool>
+where
+    F: Fn(&mut HyperLogLog) -> bool,
+{
+    check_key_type(client, key, DataType::String).await?;
+    let ekey = KeyEncoder::new().encode_string(key);
+    let vencoder = ValueEncoder::new();
+    let vdecoder = ValueDecoder::new();
+    for i in 0..2000 {
+        let prev = client.get(ekey.clone()).await?;
+        let decoded = prev
+            .clone()
+            .map(|v| vdecoder.decode(v))
+            .transpose()?
+            .filter(|v| !v.is_expired());
+        let (mut hll, created, expire_at) = match &decoded {
+            Some(v) => (
+                decode_hll(v)?,
+                false,
+                get_string_expire_at(client, ekey.clone(), v.expire_at).await?,
+            ),
+            None => (HyperLogLog::new(), true, None),
+        };
+        if !update(&mut hll) && !created {
+            return Ok(false);
+        }
+        let new_val = vencoder.encode_compressible_with_expire(&hll.encode(), expire_at);
+        if cas_string_value(client, ekey.clone(), prev.clone(), new_val, expire_at).await? {
+            // Previous value may be an expired chunked value
+            release_string_value(client, key, prev.as_ref()).await?;
+            register_key_type(client, key, DataType::String).await?;
+            return Ok(true);
+        }
+        sleep(std::cmp::min(i, 200)).await;
+    }
+    Err(RTError::StringError(String::from("Cannot swapped")))
+}
+
+// Returns 1 if any register is changed or key is created, otherwise 0
+pub async fn do_async_pfadd(key: &[u8], elements: Vec<Vec<u8>>) -> AsyncResult<RedisValue> {
+    let client = get_client()?;
+    let changed = cas_update_hll(&client, key, |hll| {
+        let mut changed = false;
+        for element in elements.iter() {
+            changed |= hll.add(element);
+        }
+        changed
+    }).await?;
+    Ok(resp_int(changed as i64))
+}
+
+// Count one key uses cached cardinality and writes the cache back if it is
+// invalid, many keys are merged into a temporary HyperLogLog to count.
+pub async fn do_async_pfcount(keys: Vec<Vec<u8>>) -> AsyncResult<RedisValue> {
+    let client = get_client()?;
+    if keys.len() > 1 {
+        let mut merged = HyperLogLog::new();
+        for key in keys.iter() {
+            if let Some(hll) = load_hll(&client, key).await? {
+                merged.merge(&hll);
+            }
+        }
+        return Ok(resp_int(merged.count() as i64));
+    }
+
+    let key = &keys[0];
+    let (prev, decoded) = match get_string_value(&client, key).await? {
+        Some(val) => val,
+        None => return Ok(resp_int(0)),
+    };
+    let mut hll = decode_hll(&decoded)?;
+    if let Some(card) = hll.cached_count() {
+        return Ok(resp_int(card as i64));
+    }
+    let card = hll.count();
+    // Cache is only written if value is not changed by others
+    let ekey = KeyEncoder::new().encode_string(key);
+    let expire_at = get_string_expire_at(&client, ekey.clone(), decoded.expire_at).await?;
+    let new_val = ValueEncoder::new().encode_compressible_with_expire(&hll.encode(), expire_at);
+    cas_string_value(&client, ekey, Some(prev), new_val, expire_at).await?;
+    Ok(resp_int(card as i64))
+}
+
+// Merge source keys into dest key, dest key's registers are merged too
+pub async fn do_async_pfmerge(dest: &[u8], srcs: Vec<Vec<u8>>) -> AsyncResult<RedisValue> {
+    let client = get_client()?;
+    let mut merged = HyperLogLog::new();
+    for src in srcs.iter() {
+        if let Some(hll) = load_hll(&client, src).await? {
+            merged.merge(&hll);
+        }
+    }
+    cas_update_hll(&client, dest, |hll| {
+        hll.merge(&merged);
+        true
+    }).await?;
+    Ok(resp_ok())
+}
@@ -1,6 +1,9 @@
-use super::get_client;
+use super::{
+    get_client,
+    generic::{check_key_type, register_key_type, remove_key_type},
+};
 use crate::{
-    encoding::{KeyEncoder, KeyDecoder}, commands::errors::{AsyncResult, RTError},
+    encoding::{KeyEncoder, KeyDecoder, DataType, ValueEncoder, ValueDecoder}, commands::errors::{AsyncResult, RTError},
     utils::{resp_int, sleep, resp_ok}, client::RawClientWrapper,
 };
 use redis_module::RedisValue;
@@ -59,7 +62,7 @@ async fn pop_adjust_meta(
     mkey: Key,
     dir: ListDirection,
     size: i64,
-) -> AsyncResult<(bool, (i64, i64))> {
+) -> AsyncResult<(bool, (i64, i64), i64)> {
     let decoder = KeyDecoder::new();
     for t in 0..2000 {
         let mval = client.get(mkey.clone()).await?;
@@ -69,7 +72,7 @@ async fn pop_adjust_meta(
         let key_pair: (i64, i64);
         let count = i64::min(size as i64, r - l);
         if count == 0 {
-            return Ok((false, (0, 0)));
+            return Ok((false, (0, 0), 0));
         }
         match dir {
             ListDirection::Left => {
@@ -84,7 +87,7 @@ async fn pop_adjust_meta(
         let nmval = encoder.encode_list_meta(nl, nr);
         let (_, swapped) = client.compare_and_swap(mkey.clone(), mval, nmval).await?;
         if swapped {
-            return Ok((true, key_pair));
+            return Ok((true, key_pair, nr - nl));
         }
         sleep(std::cmp::min(t, 200)).await;
     }
@@ -92,52 +95,59 @@ async fn pop_adjust_meta(
 }
 
 pub async fn do_async_push(
-    key: &str,
-    elements: Vec<String>,
+    key: &[u8],
+    elements: Vec<Vec<u8>>,
     dir: ListDirection,
 ) -> AsyncResult<RedisValue> {
     let client = get_client()?;
+    check_key_type(&client, key, DataType::List).await?;
     let encoder = KeyEncoder::new();
     let emkey = encoder.encode_list_meta_key(key);
     let (size, idxes) = push_adjust_meta(&client, &encoder, emkey, dir, elements.len()).await?;
     for (pos, e) in elements.iter().enumerate() {
         if let Some(idx) = idxes.get(pos) {
             let ekey = encoder.encode_list_elem_key(key, *idx);
-            let _ = client.put(ekey, &e.to_owned()).await?;
+            let _ = client.put(ekey, ValueEncoder::new().encode_raw(e)).await?;
         }
     }
+    register_key_type(&client, key, DataType::List).await?;
     Ok(resp_int(size))
 }
 
 pub async fn do_async_pop(
-    key: &str,
+    key: &[u8],
     count: i64,
     dir: ListDirection,
 ) -> AsyncResult<RedisValue> {
     let client = get_client()?;
+    check_key_type(&client, key, DataType::List).await?;
     let encoder = KeyEncoder::new();
     let emkey = encoder.encode_list_meta_key(key);
-    let (notempty, (lkey, rkey)) = pop_adjust_meta(&client, &encoder, emkey, dir, count).await?;
+    let (notempty, (lkey, rkey), remain) = pop_adjust_meta(&client, &encoder, emkey, dir, count).await?;
     if !notempty {
         return Ok(RedisValue::Null);
     }
+    if remain <= 0 {
+        remove_key_type(&client, key, DataType::List).await?;
+    }
     let start_key = encoder.encode_list_elem_key(key, lkey);
     let end_key = encoder.encode_list_elem_key(key, rkey);
     let range = start_key..end_key;
     let result = client.scan(range.into(), count as u32).await?;
     let mut rkeys: Vec<Key> = Vec::new();
     let mut values: Vec<Vec<u8>> = Vec::new();
-    result.into_iter()
-        .for_each(|kv| {
-            rkeys.push(kv.key().clone());
-            values.push(kv.value().to_owned().into());
-        });
+    let vdecoder = ValueDecoder::new();
+    for kv in result.into_iter() {
+        rkeys.push(kv.0);
+        values.push(vdecoder.decode(kv.1)?.data);
+    }
     let _ = client.batch_delete(rkeys).await?;
     Ok(values.into())
 }
 
-pub async fn do_async_llen(key: &str) -> AsyncResult<RedisValue> {
+pub async fn do_async_llen(key: &[u8]) -> AsyncResult<RedisValue> {
     let client = get_client()?;
+    check_key_type(&client, key, DataType::List).await?;
     let emkey = KeyEncoder::new().encode_list_meta_key(key);
     let decoder = KeyDecoder::new();
     let mval = client.get(emkey.clone()).await?;
@@ -146,11 +156,12 @@ pub async fn do_async_llen(key: &str) -> AsyncResult<RedisValue> {
 }
 
 pub async fn do_async_lrange(
-    key: &str,
+    key: &[u8],
     start: i64,
     stop: i64,
 ) -> AsyncResult<RedisValue> {
     let client = get_client()?;
+    check_key_type(&client, key, DataType::List).await?;
     let decoder = KeyDecoder::new();
     let encoder = KeyEncoder::new();
     let emkey = encoder.encode_list_meta_key(key);
@@ -170,15 +181,17 @@ pub async fn do_async_lrange(
     let end_key = encoder.encode_list_elem_key(key, end_pos);
     let range = start_key..end_key;
     let result = client.scan(range.into(), num as u32).await?;
-    let values: Vec<RedisValue> = result
+    let vdecoder = ValueDecoder::new();
+    let values = result
         .into_iter()
-        .map(|p| p.value().clone().into())
-        .collect();
+        .map(|p| -> AsyncResult<RedisValue> { Ok(vdecoder.decode(p.1)?.data.into()) })
+        .collect::<AsyncResult<Vec<RedisValue>>>()?;
     Ok(values.into())
 }
 
-pub async fn do_async_lindex(key: &str, index: i64) -> AsyncResult<RedisValue> {
+pub async fn do_async_lindex(key: &[u8], index: i64) -> AsyncResult<RedisValue> {
     let client = get_client()?;
+    check_key_type(&client, key, DataType::List).await?;
     let decoder = KeyDecoder::new();
     let encoder = KeyEncoder::new();
     let emkey = encoder.encode_list_meta_key(key);
@@ -191,16 +204,17 @@ pub async fn do_async_lindex(key: &str, index: i64) -> AsyncResult<RedisValue> {
     }
     let mkey = encoder.encode_list_elem_key(key, pos);
     let value = client.get(mkey).await?;
-    Ok(value.into())
+    Ok(value.map(|v| ValueDecoder::new().decode(v)).transpose()?.map(|v| v.data).into())
 }
 
-pub async fn do_async_ldel(key: &str) -> AsyncResult<RedisValue> {
+pub async fn do_async_ldel(key: &[u8]) -> AsyncResult<RedisValue> {
     let client = get_client()?;
     let encoder = KeyEncoder::new();
     let emkey = encoder.encode_list_meta_key(key);
     let range = encoder.encode_list_elem_start(key)..encoder.encode_list_elem_end(key);
     let _ = client.batch_delete(vec![emkey]).await?;
     let _ = client.delete_range(range.into()).await?;
+    remove_key_type(&client, key, DataType::List).await?;
     Ok(resp_ok())
 }
 
@@ -214,7 +228,7 @@ fn adjust_offset(offset: i64, l: i64, r: i64) -> i64 {
 }
 
 pub async fn do_async_ltrim(
-    key: &str,
+    key: &[u8],
     start: i64,
     stop: i64,
 ) -> AsyncResult<RedisValue> {
@@ -240,7 +254,7 @@ pub async fn do_async_ltrim(
     Ok(resp_ok())
 }
 
-pub async fn do_async_lpos(key: &str, element: &str) -> AsyncResult<RedisValue> {
+pub async fn do_async_lpos(key: &[u8], element: &str) -> AsyncResult<RedisValue> {
     let in_txn = has_txn(cid);
     let mut txn = get_transaction(cid).await?;
     let encoded_key = encode_list_meta_key(key);
@@ -8,6 +8,10 @@ pub mod hash;
 pub mod list;
 pub mod admin;
 pub mod set;
+pub mod generic;
+pub mod bitmap;
+pub mod hyperloglog;
+pub mod counter;
 
 static mut CLIENT_COUNTER: u64 = 0;
 
@@ -1,13 +1,17 @@
-use super::get_client;
+use super::{
+    get_client,
+    generic::{check_key_type, register_key_type},
+};
 use crate::{
-    encoding::{KeyEncoder, KeyDecoder, EMPTY_VALUE},
+    encoding::{KeyEncoder, KeyDecoder, DataType, EMPTY_VALUE},
     commands::errors::AsyncResult,
     utils::resp_int,
 };
 use redis_module::RedisValue;
 
-pub async fn do_async_sadd(key: &str, members: Vec<String>) -> AsyncResult<RedisValue> {
+pub async fn do_async_sadd(key: &[u8], members: Vec<Vec<u8>>) -> AsyncResult<RedisValue> {
     let client = get_client()?;
+    check_key_type(&client, key, DataType::Set).await?;
     let encoder = KeyEncoder::new();
     let mut added_num: i64 = 0;
     for m in members.iter() {
@@ -17,19 +21,22 @@ pub async fn do_async_sadd(key: &str, members: Vec<String>) -> AsyncResult<Redis
             added_num += 1;
         }
     }
+    register_key_type(&client, key, DataType::Set).await?;
     Ok(resp_int(added_num))
 }
 
-pub async fn do_async_scard(key: &str) -> AsyncResult<RedisValue> {
+pub async fn do_async_scard(key: &[u8]) -> AsyncResult<RedisValue> {
     let client = get_client()?;
+    check_key_type(&client, key, DataType::Set).await?;
     let encoder = KeyEncoder::new();
     let range = encoder.encode_set_start(key)..encoder.encode_set_end(key);
     let result = client.scan(range.into(), 10200).await?;
     Ok(resp_int(result.len() as i64))
 }
 
-pub async fn do_async_smembers(key: &str) -> AsyncResult<RedisValue> {
+pub async fn do_async_smembers(key: &[u8]) -> AsyncResult<RedisValue> {
     let client = get_client()?;
+    check_key_type(&client, key, DataType::Set).await?;
     let encoder = KeyEncoder::new();
     let decoder = KeyDecoder::new();
     let range = encoder.encode_set_start(key)..encoder.encode_set_end(key);
@@ -37,7 +44,7 @@ pub async fn do_async_smembers(key: &str) -> AsyncResult<RedisValue> {
     let values: Vec<Vec<u8>> = result
         .into_iter()
         .map(|p| {
-            Vec::from(decoder.decode_set_member(p.key().to_owned(), key))
+            Vec::from(decoder.decode_set_member(p.key().to_owned()))
         })
         .collect();
     Ok(values.into())
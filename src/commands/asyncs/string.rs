@@ -1,121 +1,620 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use redis_module::{RedisValue, ThreadSafeContext, BlockedClient};
 use tikv_client::{Key, Value, KvPair};
 use crate::{
+    client::RawClientWrapper,
     commands::errors::{AsyncResult, RTError},
-    encoding::{KeyEncoder, KeyDecoder},
-    utils::{resp_int, sleep},
+    encoding::{
+        KeyEncoder, KeyDecoder, DataType, ValueEncoder, ValueDecoder, ValueType, DecodedValue,
+        ChunkManifest, CHUNK_SIZE, now_millis, ttl_from_expire_at,
+    },
+    utils::{resp_int, resp_ok, sleep, parse_redis_int, parse_redis_float, format_redis_float},
+};
+use super::{
+    get_client,
+    generic::{
+        check_key_type, get_key_type, register_key_type, remove_key_type, reset_key_type,
+        reset_keys_type, purge_key_data, claim_key_data,
+    },
+    hash::hash_exists,
 };
-use super::get_client;
 
-pub async fn do_async_rawkv_batch_del(keys: Vec<String>) -> AsyncResult<RedisValue> {
-    let client = get_client()?;
-    let num_keys = keys.len();
-    let ekeys = KeyEncoder::new().encode_strings(keys);
-    let _ = client.batch_delete(ekeys).await?;
-    Ok(resp_int(num_keys as i64))
+// Options of SET command, `expire_at` is unix timestamp in milliseconds
+#[derive(Default)]
+pub struct SetOptions {
+    pub expire_at: Option<u64>,
+    pub nx: bool,
+    pub xx: bool,
+    pub get: bool,
+    pub keep_ttl: bool,
 }
 
-pub async fn do_async_rawkv_put_not_exists(key: &str, value: &str) -> AsyncResult<RedisValue> {
-    let client = get_client()?;
+impl SetOptions {
+    pub fn is_plain(&self) -> bool {
+        self.expire_at.is_none() && !self.nx && !self.xx && !self.get && !self.keep_ttl
+    }
+}
+
+// Compare and swap string value, TiKV TTL is set if value has expire time.
+pub async fn cas_string_value(
+    client: &RawClientWrapper,
+    ekey: Key,
+    prev: Option<Vec<u8>>,
+    val: Vec<u8>,
+    expire_at: Option<u64>,
+) -> AsyncResult<bool> {
+    let (_, swapped) = match expire_at {
+        Some(ts) => client.compare_and_swap_with_ttl(ekey, prev, val, ttl_from_expire_at(ts)).await?,
+        None => client.compare_and_swap(ekey, prev, val).await?,
+    };
+    Ok(swapped)
+}
+
+// Expired empty value, used to delete a string value by compare and swap. It
+// is swapped in without TiKV TTL and deleted by `remove_deleted_value`, so
+// deleting does not need TTL enabled in TiKV. One left by a stopped module is
+// treated as not exists and replaced by later writes.
+pub fn deleted_string_value() -> Vec<u8> {
+    ValueEncoder::new().encode_with_flags(ValueType::Raw, 0, Some(0), &[])
+}
+
+// Delete the expired value swapped in by `deleted_string_value`, so deleted
+// keys do not depend on TiKV TTL. RawKV API has no compare and delete, the
+// value is checked before deleting, a value written by others right between
+// the check and the delete is lost as a concurrent delete.
+pub async fn remove_deleted_value(client: &RawClientWrapper, ekey: Key) -> AsyncResult<()> {
+    if client.get(ekey.clone()).await? == Some(deleted_string_value()) {
+        client.batch_delete(vec![ekey]).await?;
+    }
+    Ok(())
+}
+
+// Get expire time of a stored string value in milliseconds. Value's TTL may be
+// set by TiKV TTL only (such as written by old versions), so fallback to TiKV.
+pub async fn get_string_expire_at(
+    client: &RawClientWrapper,
+    ekey: Key,
+    expire_at: Option<u64>,
+) -> AsyncResult<Option<u64>> {
+    if expire_at.is_some() {
+        return Ok(expire_at);
+    }
+    let ttl = client.get_ttl(ekey).await?;
+    if ttl > 0 {
+        Ok(Some(now_millis() + ttl as u64 * 1000))
+    } else {
+        Ok(None)
+    }
+}
+
+// Get string value of key, returns None if key not exists or expired and
+// WRONGTYPE if key holds other data type.
+pub async fn get_string_value(
+    client: &RawClientWrapper,
+    key: &[u8],
+) -> AsyncResult<Option<(Vec<u8>, DecodedValue)>> {
+    check_key_type(client, key, DataType::String).await?;
     let ekey = KeyEncoder::new().encode_string(key);
-    let (_, swapped) = client.compare_and_swap(ekey, None.into(), value.into()).await?;
-    if swapped {
-        Ok(RedisValue::Integer(1))
+    let prev = match client.get(ekey).await? {
+        Some(val) => val,
+        None => return Ok(None),
+    };
+    let decoded = ValueDecoder::new().decode(prev.clone())?;
+    if decoded.is_expired() {
+        return Ok(None);
+    }
+    Ok(Some((prev, decoded)))
+}
+
+// Length of string value in bytes
+pub fn string_value_len(decoded: &DecodedValue) -> u64 {
+    match decoded.manifest() {
+        Some(m) => m.len,
+        None => decoded.data.len() as u64,
+    }
+}
+
+// Get whole data of string value, chunked value is read from its chunks.
+pub async fn load_string_data(
+    client: &RawClientWrapper,
+    key: &[u8],
+    decoded: DecodedValue,
+) -> AsyncResult<Vec<u8>> {
+    match decoded.manifest() {
+        Some(m) => read_string_chunks(client, key, m, 0, m.len).await,
+        None => Ok(decoded.data),
+    }
+}
+
+// Get bytes `[start, end)` of string value, range is truncated by value length.
+pub async fn read_string_range(
+    client: &RawClientWrapper,
+    key: &[u8],
+    decoded: &DecodedValue,
+    start: u64,
+    end: u64,
+) -> AsyncResult<Vec<u8>> {
+    let end = end.min(string_value_len(decoded));
+    if start >= end {
+        return Ok(Vec::new());
+    }
+    match decoded.manifest() {
+        Some(m) => read_string_chunks(client, key, m, start, end).await,
+        None => Ok(decoded.data[start as usize..end as usize].to_vec()),
+    }
+}
+
+// Chunks read or written in one request, at most 4MB data
+const CHUNK_BATCH: u64 = 64;
+
+// Read bytes `[start, end)` of chunked value, only chunks in range are read
+// and missing chunks are treated as zero bytes.
+async fn read_string_chunks(
+    client: &RawClientWrapper,
+    key: &[u8],
+    m: ChunkManifest,
+    start: u64,
+    end: u64,
+) -> AsyncResult<Vec<u8>> {
+    let end = end.min(m.len);
+    if start >= end {
+        return Ok(Vec::new());
+    }
+    let encoder = KeyEncoder::new();
+    let chunk_size = CHUNK_SIZE as u64;
+    let last = (end - 1) / chunk_size;
+    let mut ret = vec![0u8; (end - start) as usize];
+    let mut first = start / chunk_size;
+    while first <= last {
+        let to = (first + CHUNK_BATCH).min(last + 1);
+        let range = encoder.encode_string_chunk(key, m.gen, first)..encoder.encode_string_chunk(key, m.gen, to);
+        let result = client.scan(range.into(), (to - first) as u32).await?;
+        for kv in result.into_iter() {
+            let rkey: Vec<u8> = kv.0.into();
+            let idx = u64::from_be_bytes(rkey[rkey.len() - 8..].try_into().unwrap());
+            let chunk_start = idx * chunk_size;
+            let from = start.max(chunk_start);
+            let to = end.min(chunk_start + kv.1.len() as u64);
+            if from < to {
+                ret[(from - start) as usize..(to - start) as usize]
+                    .copy_from_slice(&kv.1[(from - chunk_start) as usize..(to - chunk_start) as usize]);
+            }
+        }
+        first = to;
+    }
+    Ok(ret)
+}
+
+// Write data into chunks of a new chunked value and returns its manifest. All
+// zero chunks are not written. Chunks are given the same TiKV TTL as the value,
+// so they are removed together with the manifest.
+pub async fn write_string_chunks(
+    client: &RawClientWrapper,
+    key: &[u8],
+    data: &[u8],
+    len: u64,
+    expire_at: Option<u64>,
+) -> AsyncResult<ChunkManifest> {
+    let encoder = KeyEncoder::new();
+    let m = ChunkManifest::new(len.max(data.len() as u64));
+    let kvs: Vec<KvPair> = data
+        .chunks(CHUNK_SIZE)
+        .enumerate()
+        .filter(|(_, chunk)| chunk.iter().any(|b| *b != 0))
+        .map(|(idx, chunk)| KvPair::from((encoder.encode_string_chunk(key, m.gen, idx as u64), chunk.to_vec())))
+        .collect();
+    if expire_at.is_some() {
+        // Batch put can not set TTL, chunks of a new generation have no
+        // previous value so compare and swap always succeeds.
+        for kv in kvs.into_iter() {
+            cas_string_value(client, kv.0, None, kv.1, expire_at).await?;
+        }
+        return Ok(m);
+    }
+    for batch in kvs.chunks(CHUNK_BATCH as usize) {
+        client.batch_put(batch.to_vec()).await?;
+    }
+    Ok(m)
+}
+
+// Set TiKV TTL of one chunk to the value's expire time, the chunk is kept if
+// it is deleted or changed by others.
+pub async fn expire_string_chunk(
+    client: &RawClientWrapper,
+    ckey: Key,
+    prev: Option<Vec<u8>>,
+    expire_at: Option<u64>,
+) -> AsyncResult<()> {
+    let mut prev = prev;
+    for i in 0..2000 {
+        let chunk = match prev {
+            Some(chunk) => chunk,
+            None => return Ok(()),
+        };
+        if cas_string_value(client, ckey.clone(), Some(chunk.clone()), chunk, expire_at).await? {
+            return Ok(());
+        }
+        sleep(std::cmp::min(i, 200)).await;
+        prev = client.get(ckey.clone()).await?;
+    }
+    Err(RTError::StringError(String::from("Cannot swapped")))
+}
+
+// Set TiKV TTL of all chunks after the value's expire time is changed. Chunks
+// changed by chunked SETBIT at the same time are set by SETBIT, as it checks
+// the expire time again after the chunk is written.
+pub async fn expire_string_chunks(
+    client: &RawClientWrapper,
+    key: &[u8],
+    m: ChunkManifest,
+    expire_at: Option<u64>,
+) -> AsyncResult<()> {
+    let encoder = KeyEncoder::new();
+    let num_chunks = (m.len + CHUNK_SIZE as u64 - 1) / CHUNK_SIZE as u64;
+    let mut first = 0;
+    while first < num_chunks {
+        let to = (first + CHUNK_BATCH).min(num_chunks);
+        let range = encoder.encode_string_chunk(key, m.gen, first)..encoder.encode_string_chunk(key, m.gen, to);
+        for kv in client.scan(range.into(), (to - first) as u32).await?.into_iter() {
+            expire_string_chunk(client, kv.0, Some(kv.1), expire_at).await?;
+        }
+        first = to;
+    }
+    Ok(())
+}
+
+// Delete chunks of one chunked value
+pub async fn delete_string_chunks(
+    client: &RawClientWrapper,
+    key: &[u8],
+    m: ChunkManifest,
+) -> AsyncResult<()> {
+    let encoder = KeyEncoder::new();
+    let range = encoder.encode_string_chunk(key, m.gen, 0)..encoder.encode_string_chunk(key, m.gen, u64::MAX);
+    client.delete_range(range.into()).await?;
+    Ok(())
+}
+
+// Write chunks of data if it is larger than `CHUNK_SIZE`, returns manifest of
+// the new chunked value. Chunks should be written before the manifest is
+// swapped into value key.
+pub async fn write_string_data(
+    client: &RawClientWrapper,
+    key: &[u8],
+    data: &[u8],
+    expire_at: Option<u64>,
+) -> AsyncResult<Option<ChunkManifest>> {
+    if data.len() > CHUNK_SIZE {
+        Ok(Some(write_string_chunks(client, key, data, data.len() as u64, expire_at).await?))
     } else {
-        Ok(RedisValue::Integer(0))
-    } 
+        Ok(None)
+    }
+}
+
+// Encode string value of data, `m` is returned by `write_string_data`
+pub fn encode_string_data(data: &[u8], m: Option<ChunkManifest>, expire_at: Option<u64>) -> Vec<u8> {
+    let vencoder = ValueEncoder::new();
+    match m {
+        Some(m) => vencoder.encode_with_flags(ValueType::Chunked, 0, expire_at, &m.encode()),
+        None => vencoder.encode_compressible_with_expire(data, expire_at),
+    }
 }
 
-pub async fn do_async_rawkv_get(key: &str) -> AsyncResult<RedisValue> {
+// Delete chunks written by `write_string_data` if the value is not swapped
+pub async fn discard_string_data(
+    client: &RawClientWrapper,
+    key: &[u8],
+    m: Option<ChunkManifest>,
+) -> AsyncResult<()> {
+    match m {
+        Some(m) => delete_string_chunks(client, key, m).await,
+        None => Ok(()),
+    }
+}
+
+// Delete chunks of string value if it is chunked, used after the value is
+// overwritten or deleted.
+pub async fn release_string_value(
+    client: &RawClientWrapper,
+    key: &[u8],
+    value: Option<&Vec<u8>>,
+) -> AsyncResult<()> {
+    // Corrupt value can not be chunked, chunked value is never compressed
+    let decoded = value.and_then(|v| ValueDecoder::new().decode(v.clone()).ok());
+    if let Some(m) = decoded.and_then(|v| v.manifest()) {
+        delete_string_chunks(client, key, m).await?;
+    }
+    Ok(())
+}
+
+// Overwrite string value, data larger than `CHUNK_SIZE` is stored as chunked
+// value. Chunks of previous value are deleted after new value written.
+pub async fn put_string_data(
+    client: &RawClientWrapper,
+    key: &[u8],
+    data: &[u8],
+    expire_at: Option<u64>,
+) -> AsyncResult<()> {
+    let ekey = KeyEncoder::new().encode_string(key);
+    let m = write_string_data(client, key, data, expire_at).await?;
+    let new_val = encode_string_data(data, m, expire_at);
+    // Swap with the exact previous value, so its chunks can be deleted
+    for i in 0..2000 {
+        let prev = client.get(ekey.clone()).await?;
+        if cas_string_value(client, ekey.clone(), prev.clone(), new_val.clone(), expire_at).await? {
+            release_string_value(client, key, prev.as_ref()).await?;
+            return Ok(());
+        }
+        sleep(std::cmp::min(i, 200)).await;
+    }
+    discard_string_data(client, key, m).await?;
+    Err(RTError::StringError(String::from("Cannot swapped")))
+}
+
+// Swap live string value to an expired value, returns true if key existed.
+// Only one of the concurrent deletes can swap the value, so each deleted key
+// is counted once. Chunks of previous value are released.
+pub async fn delete_string_value(client: &RawClientWrapper, key: &[u8]) -> AsyncResult<bool> {
+    let ekey = KeyEncoder::new().encode_string(key);
+    let vdecoder = ValueDecoder::new();
+    for i in 0..2000 {
+        let prev = match client.get(ekey.clone()).await? {
+            Some(val) => val,
+            None => return Ok(false),
+        };
+        // Corrupt value is deleted as a live value
+        if vdecoder.decode(prev.clone()).map_or(false, |v| v.is_expired()) {
+            // Expired value may still have chunks
+            release_string_value(client, key, Some(&prev)).await?;
+            return Ok(false);
+        }
+        if cas_string_value(client, ekey.clone(), Some(prev.clone()), deleted_string_value(), None).await? {
+            remove_deleted_value(client, ekey).await?;
+            release_string_value(client, key, Some(&prev)).await?;
+            return Ok(true);
+        }
+        sleep(std::cmp::min(i, 200)).await;
+    }
+    Err(RTError::StringError(String::from("Cannot swapped")))
+}
+
+// Delete keys whatever type they hold, keys not in type registry are deleted
+// as string. Returns number of keys that existed, repeated keys are counted
+// once as Redis does.
+pub async fn do_async_rawkv_batch_del(keys: Vec<Vec<u8>>) -> AsyncResult<RedisValue> {
+    let client = get_client()?;
+    let encoder = KeyEncoder::new();
+    let decoder = KeyDecoder::new();
+    let mut seen: HashSet<&Vec<u8>> = HashSet::new();
+    let keys: Vec<&Vec<u8>> = keys.iter().filter(|k| seen.insert(*k)).collect();
+    let tkeys: Vec<Key> = keys.iter().map(|k| encoder.encode_type_key(k)).collect();
+    let types: HashMap<Key, Value> = client
+        .batch_get(tkeys.clone())
+        .await?
+        .into_iter()
+        .map(|pair| (pair.0, pair.1))
+        .collect();
+    let mut num_deleted: i64 = 0;
+    let mut ekeys: Vec<Key> = Vec::new();
+    for (key, tkey) in keys.into_iter().zip(tkeys.into_iter()) {
+        let existed = match types.get(&tkey).and_then(|v| decoder.decode_type(v)) {
+            Some(DataType::String) | None => delete_string_value(&client, key).await?,
+            Some(tp) => {
+                let existed = claim_key_data(&client, key, tp).await?;
+                purge_key_data(&client, key, tp).await?;
+                existed
+            }
+        };
+        if existed {
+            num_deleted += 1;
+        }
+        if types.contains_key(&tkey) {
+            ekeys.push(tkey);
+        }
+    }
+    if !ekeys.is_empty() {
+        client.batch_delete(ekeys).await?;
+    }
+    Ok(resp_int(num_deleted))
+}
+
+pub async fn do_async_rawkv_put_not_exists(key: &[u8], value: &[u8]) -> AsyncResult<RedisValue> {
     let client = get_client()?;
+    match check_key_type(&client, key, DataType::String).await {
+        Err(RTError::WrongType) => return Ok(resp_int(0)),
+        other => other?,
+    }
     let ekey = KeyEncoder::new().encode_string(key);
-    let val = client.get(ekey).await?;
-    Ok(val.into())
+    let vdecoder = ValueDecoder::new();
+    let m = write_string_data(&client, key, value, None).await?;
+    let evalue = encode_string_data(value, m, None);
+    for i in 0..2000 {
+        let prev = client.get(ekey.clone()).await?;
+        // Expired values are kept until TiKV removes them by TTL, they and
+        // deleted values left by a stopped module are treated as not exists
+        // as SET NX does.
+        if vdecoder.decode_opt(prev.clone())?.is_some() {
+            discard_string_data(&client, key, m).await?;
+            return Ok(RedisValue::Integer(0));
+        }
+        if cas_string_value(&client, ekey.clone(), prev.clone(), evalue.clone(), None).await? {
+            // Expired value's chunks may still exist
+            release_string_value(&client, key, prev.as_ref()).await?;
+            register_key_type(&client, key, DataType::String).await?;
+            return Ok(RedisValue::Integer(1));
+        }
+        sleep(std::cmp::min(i, 200)).await;
+    }
+    discard_string_data(&client, key, m).await?;
+    Err(RTError::StringError(String::from("Cannot swapped")))
 }
 
-pub async fn do_async_rawkv_put(key: &str, val: &str) -> AsyncResult<RedisValue> {
+pub async fn do_async_rawkv_get(key: &[u8]) -> AsyncResult<RedisValue> {
     let client = get_client()?;
+    match get_string_value(&client, key).await? {
+        Some((_, decoded)) => Ok(load_string_data(&client, key, decoded).await?.into()),
+        None => Ok(RedisValue::Null),
+    }
+}
+
+pub async fn do_async_rawkv_put(key: &[u8], val: &[u8]) -> AsyncResult<RedisValue> {
+    let client = get_client()?;
+    put_string_data(&client, key, val, None).await?;
+    reset_key_type(&client, key, DataType::String).await?;
+    Ok(resp_ok())
+}
+
+pub async fn do_async_rawkv_set(
+    key: &[u8],
+    val: &[u8],
+    opts: SetOptions,
+) -> AsyncResult<RedisValue> {
+    if opts.is_plain() {
+        return do_async_rawkv_put(key, val).await;
+    }
+    let client = get_client()?;
+    // Key holds other type is treated as exists, its data is deleted by
+    // `reset_key_type` after the new value is written.
+    let other_type = match get_key_type(&client, key).await? {
+        Some(DataType::String) | None => false,
+        Some(_) if opts.get => return Err(RTError::WrongType),
+        Some(_) if opts.nx => return Ok(RedisValue::Null),
+        Some(_) => true,
+    };
     let ekey = KeyEncoder::new().encode_string(key);
-    let _ = client.put(ekey, val).await?;
-    Ok(resp_int(1))
+    let vdecoder = ValueDecoder::new();
+    // Chunks are written only once, their TTL is changed after the swap if
+    // KEEPTTL keeps a different expire time.
+    let m = write_string_data(&client, key, val, opts.expire_at).await?;
+    let mut old_val: Option<Vec<u8>> = None;
+    let mut swapped = false;
+    for i in 0..2000 {
+        let prev = client.get(ekey.clone()).await?;
+        let decoded = prev
+            .clone()
+            .map(|v| vdecoder.decode(v))
+            .transpose()?
+            .filter(|v| !v.is_expired());
+        let exists = decoded.is_some() || other_type;
+        let expire_at = match &decoded {
+            Some(v) if opts.keep_ttl => get_string_expire_at(&client, ekey.clone(), v.expire_at).await?,
+            _ => opts.expire_at,
+        };
+        // Old value should be read before its chunks are deleted
+        let old_data = match decoded {
+            Some(v) if opts.get => Some(load_string_data(&client, key, v).await?),
+            _ => None,
+        };
+        if (opts.nx && exists) || (opts.xx && !exists) {
+            discard_string_data(&client, key, m).await?;
+            if opts.get {
+                return Ok(old_data.into());
+            }
+            return Ok(RedisValue::Null);
+        }
+        let new_val = encode_string_data(val, m, expire_at);
+        if cas_string_value(&client, ekey.clone(), prev.clone(), new_val, expire_at).await? {
+            if let Some(m) = m.filter(|_| expire_at != opts.expire_at) {
+                expire_string_chunks(&client, key, m, expire_at).await?;
+            }
+            release_string_value(&client, key, prev.as_ref()).await?;
+            old_val = old_data;
+            swapped = true;
+            break;
+        }
+        sleep(std::cmp::min(i, 200)).await;
+    }
+    if !swapped {
+        discard_string_data(&client, key, m).await?;
+        return Err(RTError::StringError(String::from("Cannot swapped")));
+    }
+    reset_key_type(&client, key, DataType::String).await?;
+    if opts.get {
+        Ok(old_val.into())
+    } else {
+        Ok(resp_ok())
+    }
 }
 
-pub async fn do_async_rawkv_scan(prefix: &str, limit: u64) -> AsyncResult<RedisValue> {
+pub async fn do_async_rawkv_scan(prefix: &[u8], limit: u64) -> AsyncResult<RedisValue> {
     let client = get_client()?;
     let encoder = KeyEncoder::new();
     let decoder = KeyDecoder::new();
+    let vdecoder = ValueDecoder::new();
     let range = encoder.encode_string(prefix)..encoder.encode_string_end();
     let result = client.scan(range.into(), limit as u32).await?;
-    let values: Vec<_> = result
-        .into_iter()
-        .map(|p| {
-            Vec::from([
-                decoder.decode_string(p.key().to_owned()),
-                Into::<Vec<u8>>::into(p.value().clone()),
-            ])
-        })
-        .collect();
+    let mut values: Vec<Vec<Vec<u8>>> = Vec::new();
+    for p in result.into_iter() {
+        let decoded = vdecoder.decode(p.1)?;
+        if decoded.is_expired() {
+            continue;
+        }
+        let key = decoder.decode_string(p.0);
+        let data = load_string_data(&client, &key, decoded).await?;
+        values.push(Vec::from([key, data]));
+    }
     Ok(values.into())
 }
 
 pub async fn do_async_rawkv_scan_range(
-    start_key: &str,
-    end_key: &str,
+    start_key: &[u8],
+    end_key: &[u8],
     limit: u64,
 ) -> AsyncResult<RedisValue> {
     let client = get_client()?;
     let encoder = KeyEncoder::new();
     let decoder = KeyDecoder::new();
+    let vdecoder = ValueDecoder::new();
     let range = encoder.encode_string(start_key)..encoder.encode_string(end_key);
     let result = client.scan(range.into(), limit as u32).await?;
-    let values: Vec<_> = result
-        .into_iter()
-        .map(|p| {
-            Vec::from([
-                decoder.decode_string(p.key().to_owned()),
-                Into::<Vec<u8>>::into(p.value().to_owned()),
-            ])
-        })
-        .collect();
+    let mut values: Vec<Vec<Vec<u8>>> = Vec::new();
+    for p in result.into_iter() {
+        let decoded = vdecoder.decode(p.1)?;
+        if decoded.is_expired() {
+            continue;
+        }
+        let key = decoder.decode_string(p.0);
+        let data = load_string_data(&client, &key, decoded).await?;
+        values.push(Vec::from([key, data]));
+    }
     Ok(values.into())
 }
 
 pub async fn do_async_rawkv_cached_get(
     ctx: &ThreadSafeContext<BlockedClient>,
-    key: String,
+    key: Vec<u8>,
 ) -> AsyncResult<RedisValue> {
     let client = get_client()?;
+    let decoded = match get_string_value(&client, &key).await? {
+        Some((_, decoded)) => decoded,
+        None => return Ok(RedisValue::Null),
+    };
     let ekey = KeyEncoder::new().encode_string(&key);
-    let value = client.get(ekey).await?;
-    if value.is_none() {
-        return Ok(RedisValue::Null);
+    let expire_at = get_string_expire_at(&client, ekey, decoded.expire_at).await?;
+    let value = load_string_data(&client, &key, decoded).await?;
+    // Cached value has no expire time, so value with expire time is not cached
+    if expire_at.is_some() {
+        return Ok(value.into());
     }
 
-    let data = String::from_utf8(value.clone().unwrap());
-    if data.is_ok() {
-        match ctx.lock().call("TIKV.REDIS_SET", &[&key, &data.unwrap()]) {
-            Err(err) => {
-                return Err(RTError::StringError(err.to_string()));
-            }
-            _ => {}
+    match ctx.lock().call("TIKV.REDIS_SET", &[key.as_slice(), value.as_slice()]) {
+        Err(err) => {
+            return Err(RTError::StringError(err.to_string()));
         }
-    }
-    match value {
-        Some(val) => Ok(val.into()),
-        _ => Ok(RedisValue::Null),
-    }
+        _ => {}
+    };
+    Ok(value.into())
 }
 
 pub async fn do_async_rawkv_cached_put(
     ctx: &ThreadSafeContext<BlockedClient>,
-    key: String,
-    val: &str,
+    key: &[u8],
+    val: &[u8],
 ) -> AsyncResult<RedisValue> {
     let client = get_client()?;
-    let ekey = KeyEncoder::new().encode_string(&key.clone());
-    let _ = client.put(ekey, val).await?;
-    match ctx.lock().call("TIKV.REDIS_SET", &[&key.clone(), val]) {
+    put_string_data(&client, key, val, None).await?;
+    reset_key_type(&client, key, DataType::String).await?;
+    match ctx.lock().call("TIKV.REDIS_SET", &[key, val]) {
         Err(err) => {
             return Err(RTError::StringError(err.to_string()));
         }
@@ -125,121 +624,604 @@ pub async fn do_async_rawkv_cached_put(
 }
 
 pub async fn do_async_rawkv_incr(
-    key: String, inc: bool, step: i64
+    key: &[u8], inc: bool, step: i64
 ) -> AsyncResult<RedisValue> {
     let client = get_client()?;
-    let ekey = KeyEncoder::new().encode_string(&key.clone());
-    let mut new_int: i64 = 0;
-    let mut swapped = false;
+    check_key_type(&client, key, DataType::String).await?;
+    let ekey = KeyEncoder::new().encode_string(key);
+    let vencoder = ValueEncoder::new();
+    let vdecoder = ValueDecoder::new();
     for i in 0..2000 {
-        let prev: Option<Vec<u8>>;
-        let prev_int: i64;
-        match client.get(ekey.clone()).await? {
-            Some(val) => match String::from_utf8_lossy(&val).parse::<i64>() {
-                Ok(ival) => {
-                    prev_int = ival;
-                    prev = Some(val.clone());
-                }
-                Err(err) => {
-                    return Err(RTError::StringError(err.to_string()));
-                }
-            },
-            None => {
-                prev = None;
-                prev_int = 0;
-            }
-        }
-        if inc {
-            new_int = prev_int + step;
+        let prev = client.get(ekey.clone()).await?;
+        let decoded = prev
+            .clone()
+            .map(|v| vdecoder.decode(v))
+            .transpose()?
+            .filter(|v| !v.is_expired());
+        let (prev_int, expire_at) = match &decoded {
+            // Chunked value is too large to be an integer
+            Some(v) if v.tp == ValueType::Chunked => return Err(RTError::NotInteger),
+            Some(v) => (
+                parse_redis_int(&v.data).ok_or(RTError::NotInteger)?,
+                get_string_expire_at(&client, ekey.clone(), v.expire_at).await?,
+            ),
+            None => (0, None),
+        };
+        let new_int = if inc {
+            prev_int.checked_add(step)
         } else {
-            new_int = prev_int - step;
-        }
-        let new_val = new_int.to_string();
-        let (_, ret) = client.compare_and_swap(ekey.clone(), prev, new_val.into()).await?;
-        if ret {
-            swapped = true;
-            break;
+            prev_int.checked_sub(step)
+        }.ok_or(RTError::Overflow)?;
+        let new_val = vencoder.encode_with_flags(
+            ValueType::Integer, 0, expire_at, new_int.to_string().as_bytes(),
+        );
+        if cas_string_value(&client, ekey.clone(), prev, new_val, expire_at).await? {
+            register_key_type(&client, key, DataType::String).await?;
+            return Ok(resp_int(new_int));
         }
         sleep(std::cmp::min(i, 200)).await;
     }
-    if !swapped {
-        Err(RTError::StringError(String::from("Cannot swapped")))
-    } else {
-        Ok(resp_int(new_int))
+    Err(RTError::StringError(String::from("Cannot swapped")))
+}
+
+pub async fn do_async_rawkv_incr_float(key: &[u8], step: f64) -> AsyncResult<RedisValue> {
+    let client = get_client()?;
+    check_key_type(&client, key, DataType::String).await?;
+    let ekey = KeyEncoder::new().encode_string(key);
+    let vencoder = ValueEncoder::new();
+    let vdecoder = ValueDecoder::new();
+    for i in 0..2000 {
+        let prev = client.get(ekey.clone()).await?;
+        let decoded = prev
+            .clone()
+            .map(|v| vdecoder.decode(v))
+            .transpose()?
+            .filter(|v| !v.is_expired());
+        let (prev_float, expire_at) = match &decoded {
+            Some(v) if v.tp == ValueType::Chunked => return Err(RTError::NotFloat),
+            Some(v) => (
+                parse_redis_float(&v.data).ok_or(RTError::NotFloat)?,
+                get_string_expire_at(&client, ekey.clone(), v.expire_at).await?,
+            ),
+            None => (0.0, None),
+        };
+        let new_float = prev_float + step;
+        if !new_float.is_finite() {
+            return Err(RTError::NanOrInfinity);
+        }
+        let new_data = format_redis_float(new_float);
+        let new_val = vencoder.encode_with_flags(ValueType::Raw, 0, expire_at, new_data.as_bytes());
+        if cas_string_value(&client, ekey.clone(), prev, new_val, expire_at).await? {
+            register_key_type(&client, key, DataType::String).await?;
+            return Ok(new_data.into());
+        }
+        sleep(std::cmp::min(i, 200)).await;
     }
+    Err(RTError::StringError(String::from("Cannot swapped")))
 }
 
-pub async fn do_async_rawkv_exists(keys: Vec<String>) -> AsyncResult<RedisValue> {
+// Count keys that exist, a key is counted as many times as it is given. Keys
+// registered as other types than string exist while they are registered,
+// except hashes whose fields are all expired.
+pub async fn do_async_rawkv_exists(keys: Vec<Vec<u8>>) -> AsyncResult<RedisValue> {
     let client = get_client()?;
-    let ekeys = KeyEncoder::new().encode_strings(keys);
-    let result = client.batch_get(ekeys).await?;
-    let num_items = result.len();
-    Ok(resp_int(num_items as i64))
+    let encoder = KeyEncoder::new();
+    let decoder = KeyDecoder::new();
+    let vdecoder = ValueDecoder::new();
+    let tkeys: Vec<Key> = keys.iter().map(|k| encoder.encode_type_key(k)).collect();
+    let types: HashMap<Key, Value> = client
+        .batch_get(tkeys.clone())
+        .await?
+        .into_iter()
+        .map(|pair| (pair.0, pair.1))
+        .collect();
+    let ekeys = encoder.encode_strings(keys.clone());
+    let values: HashMap<Key, Value> = client
+        .batch_get(ekeys.clone())
+        .await?
+        .into_iter()
+        .map(|pair| (pair.0, pair.1))
+        .collect();
+    let mut num_exists: i64 = 0;
+    for ((key, tkey), ekey) in keys.iter().zip(tkeys.iter()).zip(ekeys.iter()) {
+        let exists = match types.get(tkey).and_then(|v| decoder.decode_type(v)) {
+            Some(DataType::String) | None => values
+                .get(ekey)
+                .map_or(false, |v| vdecoder.decode(v.clone()).map_or(true, |v| !v.is_expired())),
+            Some(DataType::Hash) => hash_exists(&client, key).await?,
+            Some(_) => true,
+        };
+        if exists {
+            num_exists += 1;
+        }
+    }
+    Ok(resp_int(num_exists))
 }
 
-pub async fn do_async_rawkv_batch_get(keys: Vec<String>) -> AsyncResult<RedisValue> {
+// Keys hold other data types are returned as nil as Redis MGET does
+pub async fn do_async_rawkv_batch_get(keys: Vec<Vec<u8>>) -> AsyncResult<RedisValue> {
     let client = get_client()?;
-    let ekeys = KeyEncoder::new().encode_strings(keys);
+    let encoder = KeyEncoder::new();
+    let decoder = KeyDecoder::new();
+    let tkeys: Vec<Key> = keys.iter().map(|k| encoder.encode_type_key(k)).collect();
+    let types: HashMap<Key, Value> = client
+        .batch_get(tkeys.clone())
+        .await?
+        .into_iter()
+        .map(|pair| (pair.0, pair.1))
+        .collect();
+    let ekeys = encoder.encode_strings(keys.clone());
     let result = client.batch_get(ekeys.clone()).await?;
     let ret: HashMap<Key, Value> = result
         .into_iter()
         .map(|pair| (pair.0, pair.1))
         .collect();
-    let values: Vec<RedisValue> = ekeys
-        .into_iter()
-        .map(|k| {
-            let data = ret.get(Into::<Key>::into(k).as_ref());
-            match data {
-                Some(val) => val.to_owned().into(),
-                None => RedisValue::Null,
+    let vdecoder = ValueDecoder::new();
+    let mut values: Vec<RedisValue> = Vec::with_capacity(keys.len());
+    for ((key, ekey), tkey) in keys.iter().zip(ekeys.into_iter()).zip(tkeys.iter()) {
+        match types.get(tkey).and_then(|v| decoder.decode_type(v)) {
+            Some(DataType::String) | None => {}
+            Some(_) => {
+                values.push(RedisValue::Null);
+                continue;
             }
-        })
-        .collect();
+        }
+        let decoded = match ret.get(&ekey) {
+            Some(val) => vdecoder.decode(val.clone())?,
+            None => {
+                values.push(RedisValue::Null);
+                continue;
+            }
+        };
+        if decoded.is_expired() {
+            values.push(RedisValue::Null);
+            continue;
+        }
+        values.push(load_string_data(&client, key, decoded).await?.into());
+    }
     Ok(values.into())
 }
 
-pub async fn do_async_rawkv_batch_put(kvs: Vec<KvPair>) -> AsyncResult<RedisValue> {
+// Large values are written as chunked values. Previous values are read before
+// writing, so chunks of overwritten chunked values can be deleted.
+pub async fn do_async_rawkv_batch_put(kvs: Vec<(Vec<u8>, Vec<u8>)>) -> AsyncResult<RedisValue> {
     let client = get_client()?;
+    let encoder = KeyEncoder::new();
     let num_keys = kvs.len();
-    let _ = client.batch_put(kvs).await?;
+    // Same key may be given many times, the last value is used
+    let kvs: HashMap<Vec<u8>, Vec<u8>> = kvs.into_iter().collect();
+    let keys: Vec<Vec<u8>> = kvs.keys().cloned().collect();
+    let prevs = client.batch_get(encoder.encode_strings(keys.clone())).await?;
+    let mut pairs: Vec<KvPair> = Vec::with_capacity(num_keys);
+    for (key, value) in kvs.iter() {
+        let m = write_string_data(&client, key, value, None).await?;
+        pairs.push(KvPair::from((encoder.encode_string(key), encode_string_data(value, m, None))));
+    }
+    let _ = client.batch_put(pairs).await?;
+    reset_keys_type(&client, &keys, DataType::String).await?;
+    let decoder = KeyDecoder::new();
+    for kv in prevs.into_iter() {
+        let key = decoder.decode_string(kv.0);
+        release_string_value(&client, &key, Some(&kv.1)).await?;
+    }
     Ok(resp_int(num_keys as i64))
 }
 
-pub async fn do_async_rawkv_expire(key: String, ttl: u64) ->AsyncResult<RedisValue> {
+// Condition flags of EXPIRE series commands
+#[derive(Default)]
+pub struct ExpireCondition {
+    // Set only when key has no expiry
+    pub nx: bool,
+    // Set only when key has an expiry
+    pub xx: bool,
+    // Set only when new expiry is greater than current one
+    pub gt: bool,
+    // Set only when new expiry is less than current one
+    pub lt: bool,
+}
+
+impl ExpireCondition {
+    // Key without expiry is treated as infinite TTL
+    pub fn check(&self, current: Option<u64>, expire_at: u64) -> bool {
+        if self.nx && current.is_some() {
+            return false;
+        }
+        if self.xx && current.is_none() {
+            return false;
+        }
+        if self.gt && !current.map_or(false, |ts| expire_at > ts) {
+            return false;
+        }
+        if self.lt && !current.map_or(true, |ts| expire_at < ts) {
+            return false;
+        }
+        true
+    }
+}
+
+// Only string keys can have expire time. Keys hold other data types reply 0
+// to EXPIRE series commands and PERSIST, the same as keys without expire time,
+// which matches -1 returned by TTL series commands.
+async fn is_string_key(client: &RawClientWrapper, key: &[u8]) -> AsyncResult<bool> {
+    match check_key_type(client, key, DataType::String).await {
+        Err(RTError::WrongType) => Ok(false),
+        other => other.map(|_| true),
+    }
+}
+
+// Set key's expire time to `expire_at` (unix timestamp in milliseconds), key
+// is deleted if the time is passed. Returns 1 if expire time is set and 0 if
+// key not exists or condition not matched.
+pub async fn do_async_rawkv_expire_at(
+    key: &[u8],
+    expire_at: u64,
+    cond: ExpireCondition,
+) -> AsyncResult<RedisValue> {
     let client = get_client()?;
-    let ekey = KeyEncoder::new().encode_string(&key.clone());
-    let mut swapped = false;
+    if !is_string_key(&client, key).await? {
+        return Ok(resp_int(0));
+    }
+    let ekey = KeyEncoder::new().encode_string(key);
+    let vencoder = ValueEncoder::new();
+    let vdecoder = ValueDecoder::new();
     for i in 0..2000 {
-        let prev_val = client.get(ekey.clone()).await?;
-        println!("FUCK: {:?}", prev_val);
-        if prev_val.is_none() {
-            return Err(RTError::StringError(String::from("Cannot set expire with key not exists")));
-        }
-        let (val, ret) = client.compare_and_swap_with_ttl(ekey.clone(), prev_val.clone(), prev_val.clone().unwrap(), ttl).await?;
-        println!("{:?}, {:?}", val, ret);
-        if ret {
-            swapped = true;
-            break;
+        let prev = match client.get(ekey.clone()).await? {
+            Some(val) => val,
+            None => return Ok(resp_int(0)),
+        };
+        let decoded = vdecoder.decode(prev.clone())?;
+        if decoded.is_expired() {
+            return Ok(resp_int(0));
+        }
+        let current = get_string_expire_at(&client, ekey.clone(), decoded.expire_at).await?;
+        if !cond.check(current, expire_at) {
+            return Ok(resp_int(0));
+        }
+        if expire_at <= now_millis() {
+            if cas_string_value(&client, ekey.clone(), Some(prev.clone()), deleted_string_value(), None).await? {
+                remove_deleted_value(&client, ekey).await?;
+                release_string_value(&client, key, Some(&prev)).await?;
+                remove_key_type(&client, key, DataType::String).await?;
+                return Ok(resp_int(1));
+            }
         } else {
-            if let Some(data) = val {
-                if data.eq(&prev_val.unwrap()) {
-                    swapped = true;
-                    break;
+            let new_val = vencoder.replace_expire_at(&prev, Some(expire_at));
+            if cas_string_value(&client, ekey.clone(), Some(prev), new_val, Some(expire_at)).await? {
+                if let Some(m) = decoded.manifest() {
+                    expire_string_chunks(&client, key, m, Some(expire_at)).await?;
                 }
-            } 
+                return Ok(resp_int(1));
+            }
         }
         sleep(std::cmp::min(i, 200)).await;
     }
-    if !swapped {
-        Err(RTError::StringError(String::from("Cannot swapped")))
+    Err(RTError::StringError(String::from("Cannot swapped")))
+}
+
+// Remove key's expire time. Returns 1 if expire time is removed and 0 if key
+// not exists or has no expire time.
+pub async fn do_async_rawkv_persist(key: &[u8]) -> AsyncResult<RedisValue> {
+    let client = get_client()?;
+    if !is_string_key(&client, key).await? {
+        return Ok(resp_int(0));
+    }
+    let ekey = KeyEncoder::new().encode_string(key);
+    let vencoder = ValueEncoder::new();
+    let vdecoder = ValueDecoder::new();
+    for i in 0..2000 {
+        let prev = match client.get(ekey.clone()).await? {
+            Some(val) => val,
+            None => return Ok(resp_int(0)),
+        };
+        let decoded = vdecoder.decode(prev.clone())?;
+        if decoded.is_expired() {
+            return Ok(resp_int(0));
+        }
+        let current = get_string_expire_at(&client, ekey.clone(), decoded.expire_at).await?;
+        if current.is_none() {
+            return Ok(resp_int(0));
+        }
+        let new_val = vencoder.replace_expire_at(&prev, None);
+        if cas_string_value(&client, ekey.clone(), Some(prev), new_val, None).await? {
+            if let Some(m) = decoded.manifest() {
+                expire_string_chunks(&client, key, m, None).await?;
+            }
+            return Ok(resp_int(1));
+        }
+        sleep(std::cmp::min(i, 200)).await;
+    }
+    Err(RTError::StringError(String::from("Cannot swapped")))
+}
+
+// Get key's TTL in milliseconds, or in seconds if `in_ms` is false. Returns -2
+// if key not exists and -1 if key has no expire time. Only string keys can
+// have expire time, so keys hold other data types return -1.
+pub async fn do_async_rawkv_get_ttl(key: &[u8], in_ms: bool) -> AsyncResult<RedisValue> {
+    let client = get_client()?;
+    match get_key_type(&client, key).await? {
+        Some(DataType::String) | None => {}
+        Some(_) => return Ok(resp_int(-1)),
+    }
+    let ekey = KeyEncoder::new().encode_string(key);
+    let decoded = match client.get(ekey.clone()).await? {
+        Some(val) => ValueDecoder::new().decode(val)?,
+        None => return Ok(resp_int(-2)),
+    };
+    if decoded.is_expired() {
+        return Ok(resp_int(-2));
+    }
+    let ttl_ms = match get_string_expire_at(&client, ekey, decoded.expire_at).await? {
+        Some(ts) => ts.saturating_sub(now_millis()),
+        None => return Ok(resp_int(-1)),
+    };
+    if in_ms {
+        Ok(resp_int(ttl_ms as i64))
     } else {
-        Ok(resp_int(1))
+        Ok(resp_int(((ttl_ms + 500) / 1000) as i64))
     }
 }
 
-pub async fn do_async_rawkv_get_ttl(key: String) -> AsyncResult<RedisValue> {
+// Return string value of key and delete it. Value is swapped to an expired
+// value before it is deleted, so only one of the concurrent GETDEL calls can
+// get the value.
+pub async fn do_async_rawkv_getdel(key: &[u8]) -> AsyncResult<RedisValue> {
     let client = get_client()?;
-    let ekey = KeyEncoder::new().encode_string(&key);
-    let val = client.get_ttl(ekey).await?;
-    Ok(resp_int(val as i64))    
-}
\ No newline at end of file
+    check_key_type(&client, key, DataType::String).await?;
+    let ekey = KeyEncoder::new().encode_string(key);
+    let vdecoder = ValueDecoder::new();
+    let deleted = deleted_string_value();
+    for i in 0..2000 {
+        let prev = client.get(ekey.clone()).await?;
+        let decoded = match prev.clone().map(|v| vdecoder.decode(v)).transpose()? {
+            Some(v) if !v.is_expired() => v,
+            _ => return Ok(RedisValue::Null),
+        };
+        let data = load_string_data(&client, key, decoded).await?;
+        if cas_string_value(&client, ekey.clone(), prev.clone(), deleted.clone(), None).await? {
+            remove_deleted_value(&client, ekey).await?;
+            release_string_value(&client, key, prev.as_ref()).await?;
+            remove_key_type(&client, key, DataType::String).await?;
+            return Ok(data.into());
+        }
+        sleep(std::cmp::min(i, 200)).await;
+    }
+    Err(RTError::StringError(String::from("Cannot swapped")))
+}
+
+// Return string value of key and change its expire time. `expire` is None for
+// keeping current expire time and Some(None) for removing it.
+pub async fn do_async_rawkv_getex(
+    key: &[u8],
+    expire: Option<Option<u64>>,
+) -> AsyncResult<RedisValue> {
+    let client = get_client()?;
+    check_key_type(&client, key, DataType::String).await?;
+    let ekey = KeyEncoder::new().encode_string(key);
+    let vencoder = ValueEncoder::new();
+    let vdecoder = ValueDecoder::new();
+    for i in 0..2000 {
+        let prev = match client.get(ekey.clone()).await? {
+            Some(val) => val,
+            None => return Ok(RedisValue::Null),
+        };
+        let decoded = vdecoder.decode(prev.clone())?;
+        if decoded.is_expired() {
+            return Ok(RedisValue::Null);
+        }
+        let m = decoded.manifest();
+        let data = load_string_data(&client, key, decoded).await?;
+        let expire_at = match expire {
+            Some(expire_at) => expire_at,
+            None => return Ok(data.into()),
+        };
+        // Only expire time is changed, chunks of chunked value are kept
+        let new_val = vencoder.replace_expire_at(&prev, expire_at);
+        if cas_string_value(&client, ekey.clone(), Some(prev), new_val, expire_at).await? {
+            if let Some(m) = m {
+                expire_string_chunks(&client, key, m, expire_at).await?;
+            }
+            return Ok(data.into());
+        }
+        sleep(std::cmp::min(i, 200)).await;
+    }
+    Err(RTError::StringError(String::from("Cannot swapped")))
+}
+
+// Max string size is same as Redis's default proto-max-bulk-len
+pub const MAX_STRING_SIZE: usize = 512 * 1024 * 1024;
+
+// Write data at offset of chunked value in place, only chunks covering the
+// written range are rewritten by compare and swap. Bytes after the value's
+// length are not visible until the manifest is extended.
+async fn write_chunk_range(
+    client: &RawClientWrapper,
+    key: &[u8],
+    m: ChunkManifest,
+    expire_at: Option<u64>,
+    offset: u64,
+    data: &[u8],
+) -> AsyncResult<()> {
+    let encoder = KeyEncoder::new();
+    let chunk_size = CHUNK_SIZE as u64;
+    let end = offset + data.len() as u64;
+    let mut idx = offset / chunk_size;
+    while idx * chunk_size < end {
+        let chunk_start = idx * chunk_size;
+        let from = offset.max(chunk_start);
+        let to = end.min(chunk_start + chunk_size);
+        let part = &data[(from - offset) as usize..(to - offset) as usize];
+        let ckey = encoder.encode_string_chunk(key, m.gen, idx);
+        let mut swapped = false;
+        for i in 0..2000 {
+            let prev = client.get(ckey.clone()).await?;
+            let mut chunk = prev.clone().unwrap_or_default();
+            if chunk.len() < (to - chunk_start) as usize {
+                chunk.resize((to - chunk_start) as usize, 0);
+            }
+            chunk[(from - chunk_start) as usize..(to - chunk_start) as usize].copy_from_slice(part);
+            if cas_string_value(client, ckey.clone(), prev, chunk, expire_at).await? {
+                swapped = true;
+                break;
+            }
+            sleep(std::cmp::min(i, 200)).await;
+        }
+        if !swapped {
+            return Err(RTError::StringError(String::from("Cannot swapped")));
+        }
+        idx += 1;
+    }
+    Ok(())
+}
+
+// Extend length of chunked value after its chunks are written, and set TiKV
+// TTL of chunks again if expire time is changed by others meanwhile. Returns
+// false if the value is replaced by others.
+async fn extend_chunked_value(
+    client: &RawClientWrapper,
+    key: &[u8],
+    m: ChunkManifest,
+    len: u64,
+    expire_at: Option<u64>,
+) -> AsyncResult<bool> {
+    let ekey = KeyEncoder::new().encode_string(key);
+    let vencoder = ValueEncoder::new();
+    let vdecoder = ValueDecoder::new();
+    for i in 0..2000 {
+        let prev = match client.get(ekey.clone()).await? {
+            Some(val) => val,
+            None => return Ok(false),
+        };
+        let decoded = vdecoder.decode(prev.clone())?;
+        let cur = match decoded.manifest() {
+            Some(cur) if cur.gen == m.gen && !decoded.is_expired() => cur,
+            _ => return Ok(false),
+        };
+        if cur.len >= len {
+            // Chunked value always keeps its expire time in header
+            if decoded.expire_at != expire_at {
+                expire_string_chunks(client, key, cur, decoded.expire_at).await?;
+            }
+            return Ok(true);
+        }
+        let new_m = ChunkManifest { len, gen: cur.gen };
+        let new_val = vencoder.encode_with_flags(ValueType::Chunked, 0, decoded.expire_at, &new_m.encode());
+        if cas_string_value(client, ekey.clone(), Some(prev), new_val, decoded.expire_at).await? {
+            if decoded.expire_at != expire_at {
+                expire_string_chunks(client, key, new_m, decoded.expire_at).await?;
+            }
+            return Ok(true);
+        }
+        sleep(std::cmp::min(i, 200)).await;
+    }
+    Err(RTError::StringError(String::from("Cannot swapped")))
+}
+
+// Write data at offset of string value, offset None means the end of value
+// (APPEND). Expire time of the key is kept. Returns new value length.
+//
+// Chunked value is changed in place, so only chunks covering the written range
+// and the manifest are rewritten. APPEND extends the manifest before writing
+// chunks, so the range is reserved and concurrent APPENDs write different
+// ranges, readers may see zero bytes in it until the chunks are written. Other
+// writes are done in chunks first and then the manifest is extended, as SETBIT
+// does. Value not chunked is rewritten as a whole, and converted into chunked
+// value when it becomes larger than `CHUNK_SIZE`.
+async fn cas_write_string(key: &[u8], offset: Option<u64>, value: &[u8]) -> AsyncResult<u64> {
+    let client = get_client()?;
+    check_key_type(&client, key, DataType::String).await?;
+    let ekey = KeyEncoder::new().encode_string(key);
+    let vencoder = ValueEncoder::new();
+    let vdecoder = ValueDecoder::new();
+    for i in 0..2000 {
+        let prev = client.get(ekey.clone()).await?;
+        let decoded = prev
+            .clone()
+            .map(|v| vdecoder.decode(v))
+            .transpose()?
+            .filter(|v| !v.is_expired());
+        let (cur_len, expire_at) = match &decoded {
+            Some(v) => (string_value_len(v), get_string_expire_at(&client, ekey.clone(), v.expire_at).await?),
+            None => (0, None),
+        };
+        let start = offset.unwrap_or(cur_len);
+        let len = cur_len.max(start + value.len() as u64);
+        if len > MAX_STRING_SIZE as u64 {
+            return Err(RTError::StringError(String::from(
+                "ERR string exceeds maximum allowed size (proto-max-bulk-len)",
+            )));
+        }
+        if let Some(m) = decoded.as_ref().and_then(|v| v.manifest()) {
+            if offset.is_none() {
+                let new_m = ChunkManifest { len, gen: m.gen };
+                let new_val = vencoder.encode_with_flags(ValueType::Chunked, 0, expire_at, &new_m.encode());
+                if !cas_string_value(&client, ekey.clone(), prev, new_val, expire_at).await? {
+                    sleep(std::cmp::min(i, 200)).await;
+                    continue;
+                }
+            }
+            write_chunk_range(&client, key, m, expire_at, start, value).await?;
+            if extend_chunked_value(&client, key, m, len, expire_at).await? {
+                register_key_type(&client, key, DataType::String).await?;
+                return Ok(len);
+            }
+            continue;
+        }
+        let mut data = decoded.map(|v| v.data).unwrap_or_default();
+        if data.len() < len as usize {
+            data.resize(len as usize, 0);
+        }
+        data[start as usize..start as usize + value.len()].copy_from_slice(value);
+        let m = write_string_data(&client, key, &data, expire_at).await?;
+        let new_val = encode_string_data(&data, m, expire_at);
+        if cas_string_value(&client, ekey.clone(), prev.clone(), new_val, expire_at).await? {
+            // Expired value may still have chunks
+            release_string_value(&client, key, prev.as_ref()).await?;
+            register_key_type(&client, key, DataType::String).await?;
+            return Ok(len);
+        }
+        discard_string_data(&client, key, m).await?;
+        sleep(std::cmp::min(i, 200)).await;
+    }
+    Err(RTError::StringError(String::from("Cannot swapped")))
+}
+
+pub async fn do_async_rawkv_append(key: &[u8], value: &[u8]) -> AsyncResult<RedisValue> {
+    let len = cas_write_string(key, None, value).await?;
+    Ok(resp_int(len as i64))
+}
+
+pub async fn do_async_rawkv_strlen(key: &[u8]) -> AsyncResult<RedisValue> {
+    let client = get_client()?;
+    let len = match get_string_value(&client, key).await? {
+        Some((_, decoded)) => string_value_len(&decoded),
+        None => 0,
+    };
+    Ok(resp_int(len as i64))
+}
+
+pub async fn do_async_rawkv_getrange(key: &[u8], start: i64, end: i64) -> AsyncResult<RedisValue> {
+    let client = get_client()?;
+    let decoded = match get_string_value(&client, key).await? {
+        Some((_, decoded)) => decoded,
+        None => return Ok(RedisValue::StringBuffer(Vec::new())),
+    };
+    let len = string_value_len(&decoded) as i64;
+    let mut start = if start < 0 { start + len } else { start };
+    let mut end = if end < 0 { end + len } else { end };
+    start = start.max(0);
+    end = end.max(0).min(len - 1);
+    if len == 0 || start > end {
+        return Ok(RedisValue::StringBuffer(Vec::new()));
+    }
+    let data = read_string_range(&client, key, &decoded, start as u64, end as u64 + 1).await?;
+    Ok(data.into())
+}
+
+pub async fn do_async_rawkv_setrange(key: &[u8], offset: usize, value: &[u8]) -> AsyncResult<RedisValue> {
+    // Empty value does not create key or change value
+    if value.is_empty() {
+        return do_async_rawkv_strlen(key).await;
+    }
+    let len = cas_write_string(key, Some(offset as u64), value).await?;
+    Ok(resp_int(len as i64))
+}
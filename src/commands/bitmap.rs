@@ -0,0 +1,151 @@
+use crate::{
+    metrics::*,
+    commands::asyncs::bitmap::*,
+    utils::{async_execute, NextBytes},
+};
+use redis_module::{Context, NextArg, RedisError, RedisResult, RedisString};
+
+// Max bit offset is limited by max string size (512MB)
+const MAX_BIT_OFFSET: u64 = 4 * 1024 * 1024 * 1024;
+
+fn parse_bit_offset(arg: RedisString) -> Result<u64, RedisError> {
+    match arg.parse_integer() {
+        Ok(offset) if offset >= 0 && (offset as u64) < MAX_BIT_OFFSET => Ok(offset as u64),
+        _ => Err(RedisError::Str("ERR bit offset is not an integer or out of range")),
+    }
+}
+
+fn parse_bit(arg: RedisString) -> Result<bool, RedisError> {
+    match arg.parse_integer() {
+        Ok(0) => Ok(false),
+        Ok(1) => Ok(true),
+        _ => Err(RedisError::Str("ERR bit is not an integer or out of range")),
+    }
+}
+
+// Parse `BYTE | BIT` range unit, returns true if unit is BIT
+fn parse_range_unit(arg: Option<RedisString>) -> Result<bool, RedisError> {
+    let arg = match arg {
+        Some(arg) => arg,
+        None => return Ok(false),
+    };
+    let unit = arg.try_as_str()?;
+    if unit.eq_ignore_ascii_case("byte") {
+        Ok(false)
+    } else if unit.eq_ignore_ascii_case("bit") {
+        Ok(true)
+    } else {
+        Err(RedisError::Str("ERR syntax error"))
+    }
+}
+
+pub fn tikv_setbit(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    REQUEST_COUNTER.inc();
+    REQUEST_CMD_COUNTER.with_label_values(&["setbit"]).inc();
+    if args.len() != 4 {
+        return Err(RedisError::WrongArity);
+    }
+    let mut args = args.into_iter().skip(1);
+    let key = args.next_bytes()?;
+    let offset = parse_bit_offset(args.next_arg()?)?;
+    let on = parse_bit(args.next_arg()?)?;
+    async_execute(ctx, async move {
+        do_async_setbit(&key, offset, on).await
+    })
+}
+
+pub fn tikv_getbit(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    REQUEST_COUNTER.inc();
+    REQUEST_CMD_COUNTER.with_label_values(&["getbit"]).inc();
+    if args.len() != 3 {
+        return Err(RedisError::WrongArity);
+    }
+    let mut args = args.into_iter().skip(1);
+    let key = args.next_bytes()?;
+    let offset = parse_bit_offset(args.next_arg()?)?;
+    async_execute(ctx, async move {
+        do_async_getbit(&key, offset).await
+    })
+}
+
+// BITCOUNT key [start end [BYTE | BIT]]
+pub fn tikv_bitcount(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    REQUEST_COUNTER.inc();
+    REQUEST_CMD_COUNTER.with_label_values(&["bitcount"]).inc();
+    if args.len() < 2 {
+        return Err(RedisError::WrongArity);
+    }
+    if args.len() == 3 || args.len() > 5 {
+        return Err(RedisError::Str("ERR syntax error"));
+    }
+    let num_args = args.len();
+    let mut args = args.into_iter().skip(1);
+    let key = args.next_bytes()?;
+    let range = if num_args > 2 {
+        let start = args.next_i64()?;
+        let end = args.next_i64()?;
+        Some((start, end, parse_range_unit(args.next())?))
+    } else {
+        None
+    };
+    async_execute(ctx, async move {
+        do_async_bitcount(&key, range).await
+    })
+}
+
+// BITPOS key bit [start [end [BYTE | BIT]]]
+pub fn tikv_bitpos(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    REQUEST_COUNTER.inc();
+    REQUEST_CMD_COUNTER.with_label_values(&["bitpos"]).inc();
+    if args.len() < 3 {
+        return Err(RedisError::WrongArity);
+    }
+    if args.len() > 6 {
+        return Err(RedisError::Str("ERR syntax error"));
+    }
+    let mut args = args.into_iter().skip(1);
+    let key = args.next_bytes()?;
+    let bit = match args.next_arg()?.parse_integer() {
+        Ok(0) => false,
+        Ok(1) => true,
+        _ => return Err(RedisError::Str("ERR The bit argument must be 1 or 0.")),
+    };
+    let start = match args.next() {
+        Some(arg) => Some(arg.parse_integer()?),
+        None => None,
+    };
+    let end = match args.next() {
+        Some(arg) => Some(arg.parse_integer()?),
+        None => None,
+    };
+    let in_bit = parse_range_unit(args.next())?;
+    async_execute(ctx, async move {
+        do_async_bitpos(&key, bit, start, end, in_bit).await
+    })
+}
+
+// BITOP AND | OR | XOR | NOT destkey key [key ...]
+pub fn tikv_bitop(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    REQUEST_COUNTER.inc();
+    REQUEST_CMD_COUNTER.with_label_values(&["bitop"]).inc();
+    if args.len() < 4 {
+        return Err(RedisError::WrongArity);
+    }
+    let mut args = args.into_iter().skip(1);
+    let op = args.next_str()?.to_uppercase();
+    let op = match op.as_str() {
+        "AND" => BitOp::And,
+        "OR" => BitOp::Or,
+        "XOR" => BitOp::Xor,
+        "NOT" => BitOp::Not,
+        _ => return Err(RedisError::Str("ERR syntax error")),
+    };
+    let dest = args.next_bytes()?;
+    let srcs: Vec<Vec<u8>> = args.map(|arg| arg.as_slice().to_vec()).collect();
+    if op == BitOp::Not && srcs.len() != 1 {
+        return Err(RedisError::Str("ERR BITOP NOT must be called with a single source key."));
+    }
+    async_execute(ctx, async move {
+        do_async_bitop(op, &dest, srcs).await
+    })
+}
@@ -0,0 +1,44 @@
+use crate::{
+    metrics::*,
+    commands::asyncs::counter::*,
+    utils::{async_execute, NextBytes},
+};
+use redis_module::{Context, RedisError, RedisResult, RedisString};
+
+pub fn tikv_cincr(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    REQUEST_COUNTER.inc();
+    REQUEST_CMD_COUNTER.with_label_values(&["cincr"]).inc();
+    if args.len() < 2 || args.len() > 4 {
+        return Err(RedisError::WrongArity);
+    }
+    let mut args = args.into_iter().skip(1);
+    let key = args.next_bytes()?;
+    let mut step = None;
+    let mut no_sum = false;
+    for arg in args {
+        if arg.as_slice().eq_ignore_ascii_case(b"NOSUM") {
+            no_sum = true;
+        } else if step.is_none() && !no_sum {
+            step = Some(arg.parse_integer()
+                .map_err(|_| RedisError::Str("ERR value is not an integer or out of range"))?);
+        } else {
+            return Err(RedisError::Str("ERR syntax error"));
+        }
+    }
+    async_execute(ctx, async move {
+        do_async_counter_incr(&key, step.unwrap_or(1), no_sum).await
+    })
+}
+
+pub fn tikv_cgetcount(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    REQUEST_COUNTER.inc();
+    REQUEST_CMD_COUNTER.with_label_values(&["cgetcount"]).inc();
+    if args.len() != 2 {
+        return Err(RedisError::WrongArity);
+    }
+    let mut args = args.into_iter().skip(1);
+    let key = args.next_bytes()?;
+    async_execute(ctx, async move {
+        do_async_counter_get(&key).await
+    })
+}
@@ -0,0 +1,19 @@
+use crate::{
+    metrics::*,
+    commands::asyncs::generic::*,
+    utils::{async_execute, NextBytes},
+};
+use redis_module::{Context, RedisError, RedisResult, RedisString};
+
+pub fn tikv_type(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    REQUEST_COUNTER.inc();
+    REQUEST_CMD_COUNTER.with_label_values(&["type"]).inc();
+    if args.len() < 2 {
+        return Err(RedisError::WrongArity);
+    }
+    let mut args = args.into_iter().skip(1);
+    let key = args.next_bytes()?;
+    async_execute(ctx, async move {
+        do_async_type(&key).await
+    })
+}
\ No newline at end of file
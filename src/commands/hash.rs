@@ -1,14 +1,10 @@
 use crate::{
     metrics::*,
-    commands::asyncs::{
-        hash::*,
-        string::do_async_rawkv_batch_put
-    },
-    utils::async_execute,
-    encoding::KeyEncoder,
+    commands::asyncs::hash::*,
+    commands::string::{parse_expire_condition, parse_expire_time},
+    utils::{async_execute, parse_redis_float, NextBytes},
 };
 use redis_module::{Context, NextArg, RedisError, RedisResult, RedisString};
-use tikv_client::{KvPair, Key};
 
 pub fn tikv_hset(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
     REQUEST_COUNTER.inc();
@@ -16,12 +12,20 @@ pub fn tikv_hset(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
     if args.len() < 4 {
         return Err(RedisError::WrongArity);
     }
+    let num_kvs = args.len() - 2;
+    if num_kvs % 2 != 0 {
+        return Err(RedisError::WrongArity);
+    }
+    let mut fvs: Vec<(Vec<u8>, Vec<u8>)> = Vec::new();
     let mut args = args.into_iter().skip(1);
-    let key = args.next_str()?;
-    let field = args.next_str()?;
-    let value = args.next_str()?;
+    let key = args.next_bytes()?;
+    for _i in 0..num_kvs / 2 {
+        let field = args.next_bytes()?;
+        let value = args.next_bytes()?;
+        fvs.push((field, value));
+    }
     async_execute(ctx, async move {
-        do_async_hput(key, field, value).await
+        do_async_hput(&key, fvs).await
     })
 }
 
@@ -32,10 +36,10 @@ pub fn tikv_hget(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
         return Err(RedisError::WrongArity);
     }
     let mut args = args.into_iter().skip(1);
-    let key = args.next_str()?;
-    let field = args.next_str()?;
+    let key = args.next_bytes()?;
+    let field = args.next_bytes()?;
     async_execute(ctx, async move {
-        do_async_hget(key, field).await
+        do_async_hget(&key, &field).await
     })
 }
 
@@ -46,9 +50,9 @@ pub fn tikv_hget_all(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
         return Err(RedisError::WrongArity);
     }
     let mut args = args.into_iter().skip(1);
-    let key = args.next_str()?;
+    let key = args.next_bytes()?;
     async_execute(ctx, async move {
-        do_async_hscan(key).await
+        do_async_hgetall(&key).await
     })
 }
 
@@ -62,18 +66,16 @@ pub fn tikv_hmset(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
     if num_kvs % 2 != 0 {
         return Err(RedisError::WrongArity);
     }
-    let encoder = KeyEncoder::new();
-    let mut kvs: Vec<KvPair> = Vec::new();
+    let mut fvs: Vec<(Vec<u8>, Vec<u8>)> = Vec::new();
     let mut args = args.into_iter().skip(1);
-    let key = args.next_str()?;
+    let key = args.next_bytes()?;
     for _i in 0..num_kvs / 2 {
-        let field = args.next_str()?;
-        let value = args.next_str()?;
-        let kv = KvPair::from((encoder.encode_hash(key, field).to_owned(), value.to_owned()));
-        kvs.push(kv);
+        let field = args.next_bytes()?;
+        let value = args.next_bytes()?;
+        fvs.push((field, value));
     }
     async_execute(ctx, async move {
-        do_async_rawkv_batch_put(kvs).await
+        do_async_batch_hput(&key, fvs).await
     })
 }
 
@@ -83,12 +85,11 @@ pub fn tikv_hmget(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
     if args.len() < 3 {
         return Err(RedisError::WrongArity);
     }
-    let encoder = KeyEncoder::new();
     let mut args = args.into_iter().skip(1);
-    let key = args.next_str()?;
-    let fields: Vec<Key> = args.map(|s| encoder.encode_hash(key, &s.to_string())).collect();
+    let key = args.next_bytes()?;
+    let fields: Vec<Vec<u8>> = args.map(|s| s.as_slice().to_vec()).collect();
     async_execute(ctx, async move {
-        do_async_batch_hget(fields).await
+        do_async_batch_hget(&key, fields).await
     })
 }
 
@@ -99,9 +100,9 @@ pub fn tikv_hkeys(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
         return Err(RedisError::WrongArity);
     }
     let mut args = args.into_iter().skip(1);
-    let key = args.next_str()?;
+    let key = args.next_bytes()?;
     async_execute(ctx, async move {
-        do_async_hscan_fields(key).await
+        do_async_hscan_fields(&key).await
     })
 }
 
@@ -112,9 +113,9 @@ pub fn tikv_hvals(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
         return Err(RedisError::WrongArity);
     }
     let mut args = args.into_iter().skip(1);
-    let key = args.next_str()?;
+    let key = args.next_bytes()?;
     async_execute(ctx, async move {
-        do_async_hscan_values(key).await
+        do_async_hscan_values(&key).await
     })
 }
 
@@ -125,10 +126,10 @@ pub fn tikv_hexists(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
         return Err(RedisError::WrongArity);
     }
     let mut args = args.into_iter().skip(1);
-    let key = args.next_str()?;
-    let field = args.next_str()?;
+    let key = args.next_bytes()?;
+    let field = args.next_bytes()?;
     async_execute(ctx, async move {
-        do_async_hexists(key, field).await
+        do_async_hexists(&key, &field).await
     })
 }
 
@@ -138,11 +139,253 @@ pub fn tikv_hdel(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
     if args.len() < 3 {
         return Err(RedisError::WrongArity);
     }
-    let encoder = KeyEncoder::new();
     let mut args = args.into_iter().skip(1);
-    let key = args.next_str()?;
-    let fields: Vec<Key> = args.map(|s| encoder.encode_hash(key, &s.to_string())).collect();
+    let key = args.next_bytes()?;
+    let fields: Vec<Vec<u8>> = args.map(|s| s.as_slice().to_vec()).collect();
+    async_execute(ctx, async move {
+        do_async_batch_hdel(&key, fields).await
+    })
+}
+
+pub fn tikv_hlen(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    REQUEST_COUNTER.inc();
+    REQUEST_CMD_COUNTER.with_label_values(&["hlen"]).inc();
+    if args.len() != 2 {
+        return Err(RedisError::WrongArity);
+    }
+    let mut args = args.into_iter().skip(1);
+    let key = args.next_bytes()?;
+    async_execute(ctx, async move {
+        do_async_hlen(&key).await
+    })
+}
+
+pub fn tikv_hsetnx(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    REQUEST_COUNTER.inc();
+    REQUEST_CMD_COUNTER.with_label_values(&["hsetnx"]).inc();
+    if args.len() != 4 {
+        return Err(RedisError::WrongArity);
+    }
+    let mut args = args.into_iter().skip(1);
+    let key = args.next_bytes()?;
+    let field = args.next_bytes()?;
+    let value = args.next_bytes()?;
+    async_execute(ctx, async move {
+        do_async_hsetnx(&key, &field, &value).await
+    })
+}
+
+pub fn tikv_hincrby(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    REQUEST_COUNTER.inc();
+    REQUEST_CMD_COUNTER.with_label_values(&["hincrby"]).inc();
+    if args.len() != 4 {
+        return Err(RedisError::WrongArity);
+    }
+    let mut args = args.into_iter().skip(1);
+    let key = args.next_bytes()?;
+    let field = args.next_bytes()?;
+    let step = args.next_i64()?;
+    async_execute(ctx, async move {
+        do_async_hincrby(&key, &field, step).await
+    })
+}
+
+pub fn tikv_hincrbyfloat(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    REQUEST_COUNTER.inc();
+    REQUEST_CMD_COUNTER.with_label_values(&["hincrbyfloat"]).inc();
+    if args.len() != 4 {
+        return Err(RedisError::WrongArity);
+    }
+    let mut args = args.into_iter().skip(1);
+    let key = args.next_bytes()?;
+    let field = args.next_bytes()?;
+    let step = match parse_redis_float(&args.next_bytes()?) {
+        Some(val) => val,
+        None => return Err(RedisError::Str("ERR value is not a valid float")),
+    };
+    async_execute(ctx, async move {
+        do_async_hincrbyfloat(&key, &field, step).await
+    })
+}
+
+pub fn tikv_hstrlen(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    REQUEST_COUNTER.inc();
+    REQUEST_CMD_COUNTER.with_label_values(&["hstrlen"]).inc();
+    if args.len() != 3 {
+        return Err(RedisError::WrongArity);
+    }
+    let mut args = args.into_iter().skip(1);
+    let key = args.next_bytes()?;
+    let field = args.next_bytes()?;
+    async_execute(ctx, async move {
+        do_async_hstrlen(&key, &field).await
+    })
+}
+
+pub fn tikv_hscan(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    REQUEST_COUNTER.inc();
+    REQUEST_CMD_COUNTER.with_label_values(&["hscan"]).inc();
+    if args.len() < 3 {
+        return Err(RedisError::WrongArity);
+    }
+    let mut args = args.into_iter().skip(1);
+    let key = args.next_bytes()?;
+    let cursor = match decode_hscan_cursor(&args.next_bytes()?) {
+        Some(cursor) => cursor,
+        None => return Err(RedisError::Str("ERR invalid cursor")),
+    };
+    let mut pattern: Option<Vec<u8>> = None;
+    let mut count: i64 = 10;
+    while let Some(arg) = args.next() {
+        let opt = arg.try_as_str()?.to_uppercase();
+        match opt.as_str() {
+            "MATCH" => pattern = Some(args.next_bytes()?),
+            "COUNT" => {
+                count = args.next_i64()?;
+                if count < 1 {
+                    return Err(RedisError::Str("ERR syntax error"));
+                }
+            }
+            _ => return Err(RedisError::Str("ERR syntax error")),
+        }
+    }
+    // `*` matches everything, skip matching
+    if pattern.as_deref() == Some(&b"*"[..]) {
+        pattern = None;
+    }
+    let count = std::cmp::min(count, HASH_SCAN_LIMIT as i64) as u32;
+    async_execute(ctx, async move {
+        do_async_hscan(&key, cursor, pattern, count).await
+    })
+}
+
+// Parse `FIELDS numfields field [field ...]` of hash field TTL commands
+fn parse_hash_fields<T: Iterator<Item = RedisString>>(mut args: T) -> Result<Vec<Vec<u8>>, RedisError> {
+    let num_fields = args.next_i64()?;
+    if num_fields <= 0 {
+        return Err(RedisError::Str("ERR Parameter `numFields` should be greater than 0"));
+    }
+    let fields: Vec<Vec<u8>> = args.map(|s| s.as_slice().to_vec()).collect();
+    if fields.len() as i64 != num_fields {
+        return Err(RedisError::Str("ERR The `numfields` parameter must match the number of arguments"));
+    }
+    Ok(fields)
+}
+
+// Skip to the arguments after `FIELDS`, returns arguments before it
+fn split_hash_fields<T: Iterator<Item = RedisString>>(args: &mut T) -> Result<Vec<RedisString>, RedisError> {
+    let mut opts: Vec<RedisString> = Vec::new();
+    for arg in args.by_ref() {
+        if arg.as_slice().eq_ignore_ascii_case(b"FIELDS") {
+            return Ok(opts);
+        }
+        opts.push(arg);
+    }
+    Err(RedisError::Str("ERR Mandatory argument FIELDS is missing or not at the right position"))
+}
+
+fn hexpire_command(
+    ctx: &Context,
+    args: Vec<RedisString>,
+    cmd: &str,
+    in_ms: bool,
+    absolute: bool,
+) -> RedisResult {
+    REQUEST_COUNTER.inc();
+    REQUEST_CMD_COUNTER.with_label_values(&[cmd]).inc();
+    if args.len() < 6 {
+        return Err(RedisError::WrongArity);
+    }
+    let mut args = args.into_iter().skip(1);
+    let key = args.next_bytes()?;
+    let val = args.next_i64()?;
+    let cond = parse_expire_condition(split_hash_fields(&mut args)?.into_iter())?;
+    let fields = parse_hash_fields(args)?;
+    let expire_at = parse_expire_time(val, cmd, in_ms, absolute)?;
+    async_execute(ctx, async move {
+        do_async_hexpire_at(&key, fields, expire_at, cond).await
+    })
+}
+
+pub fn tikv_hexpire(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    hexpire_command(ctx, args, "hexpire", false, false)
+}
+
+pub fn tikv_hpexpire(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    hexpire_command(ctx, args, "hpexpire", true, false)
+}
+
+pub fn tikv_hexpireat(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    hexpire_command(ctx, args, "hexpireat", false, true)
+}
+
+pub fn tikv_hpexpireat(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    hexpire_command(ctx, args, "hpexpireat", true, true)
+}
+
+pub fn tikv_hpersist(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    REQUEST_COUNTER.inc();
+    REQUEST_CMD_COUNTER.with_label_values(&["hpersist"]).inc();
+    if args.len() < 5 {
+        return Err(RedisError::WrongArity);
+    }
+    let mut args = args.into_iter().skip(1);
+    let key = args.next_bytes()?;
+    if !split_hash_fields(&mut args)?.is_empty() {
+        return Err(RedisError::Str("ERR Mandatory argument FIELDS is missing or not at the right position"));
+    }
+    let fields = parse_hash_fields(args)?;
+    async_execute(ctx, async move {
+        do_async_hpersist(&key, fields).await
+    })
+}
+
+fn httl_command(ctx: &Context, args: Vec<RedisString>, cmd: &str, in_ms: bool) -> RedisResult {
+    REQUEST_COUNTER.inc();
+    REQUEST_CMD_COUNTER.with_label_values(&[cmd]).inc();
+    if args.len() < 5 {
+        return Err(RedisError::WrongArity);
+    }
+    let mut args = args.into_iter().skip(1);
+    let key = args.next_bytes()?;
+    if !split_hash_fields(&mut args)?.is_empty() {
+        return Err(RedisError::Str("ERR Mandatory argument FIELDS is missing or not at the right position"));
+    }
+    let fields = parse_hash_fields(args)?;
+    async_execute(ctx, async move {
+        do_async_httl(&key, fields, in_ms).await
+    })
+}
+
+pub fn tikv_httl(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    httl_command(ctx, args, "httl", false)
+}
+
+pub fn tikv_hpttl(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    httl_command(ctx, args, "hpttl", true)
+}
+
+pub fn tikv_hrandfield(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    REQUEST_COUNTER.inc();
+    REQUEST_CMD_COUNTER.with_label_values(&["hrandfield"]).inc();
+    if args.len() < 2 || args.len() > 4 {
+        return Err(RedisError::WrongArity);
+    }
+    let num_args = args.len();
+    let mut args = args.into_iter().skip(1);
+    let key = args.next_bytes()?;
+    let count = if num_args > 2 { Some(args.next_i64()?) } else { None };
+    let with_values = match args.next() {
+        Some(arg) if arg.as_slice().eq_ignore_ascii_case(b"WITHVALUES") => true,
+        Some(_) => return Err(RedisError::Str("ERR syntax error")),
+        None => false,
+    };
+    // Same range as Redis, so the number of replied items does not overflow
+    let max = if with_values { i64::MAX / 2 } else { i64::MAX };
+    if matches!(count, Some(count) if count < -max || count > max) {
+        return Err(RedisError::Str("ERR value is out of range"));
+    }
     async_execute(ctx, async move {
-        do_async_batch_hdel(fields).await
+        do_async_hrandfield(&key, count, with_values).await
     })
-}
\ No newline at end of file
+}
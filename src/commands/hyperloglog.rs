@@ -0,0 +1,50 @@
+use crate::{
+    metrics::*,
+    commands::asyncs::hyperloglog::*,
+    utils::{async_execute, NextBytes},
+};
+use redis_module::{Context, RedisError, RedisResult, RedisString};
+
+pub fn tikv_pfadd(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    REQUEST_COUNTER.inc();
+    REQUEST_CMD_COUNTER.with_label_values(&["pfadd"]).inc();
+    if args.len() < 2 {
+        return Err(RedisError::WrongArity);
+    }
+    let mut args = args.into_iter().skip(1);
+    let key = args.next_bytes()?;
+    let elements: Vec<Vec<u8>> = args.map(|arg| arg.as_slice().to_vec()).collect();
+    async_execute(ctx, async move {
+        do_async_pfadd(&key, elements).await
+    })
+}
+
+pub fn tikv_pfcount(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    REQUEST_COUNTER.inc();
+    REQUEST_CMD_COUNTER.with_label_values(&["pfcount"]).inc();
+    if args.len() < 2 {
+        return Err(RedisError::WrongArity);
+    }
+    let keys: Vec<Vec<u8>> = args
+        .into_iter()
+        .skip(1)
+        .map(|s| s.as_slice().to_vec())
+        .collect();
+    async_execute(ctx, async move {
+        do_async_pfcount(keys).await
+    })
+}
+
+pub fn tikv_pfmerge(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    REQUEST_COUNTER.inc();
+    REQUEST_CMD_COUNTER.with_label_values(&["pfmerge"]).inc();
+    if args.len() < 2 {
+        return Err(RedisError::WrongArity);
+    }
+    let mut args = args.into_iter().skip(1);
+    let dest = args.next_bytes()?;
+    let srcs: Vec<Vec<u8>> = args.map(|arg| arg.as_slice().to_vec()).collect();
+    async_execute(ctx, async move {
+        do_async_pfmerge(&dest, srcs).await
+    })
+}
@@ -2,7 +2,7 @@ use std::str::FromStr;
 use crate::{
     metrics::*,
     commands::asyncs::list::*,
-    utils::async_execute,
+    utils::{async_execute, NextBytes},
 };
 use redis_module::{Context, NextArg, RedisError, RedisResult, RedisString};
 
@@ -13,14 +13,14 @@ pub fn tikv_lpush(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
         return Err(RedisError::WrongArity);
     }
     let mut args = args.into_iter().skip(1);
-    let key = args.next_str()?;
-    let elements = args.map(|x| x.to_string_lossy()).collect();
+    let key = args.next_bytes()?;
+    let elements: Vec<Vec<u8>> = args.map(|x| x.as_slice().to_vec()).collect();
     ctx.log_debug(&format!(
         "Handle tikv_lpush commands, key: {}, elements: {:?}",
-        key, elements
+        String::from_utf8_lossy(&key), elements
     ));
     async_execute(ctx, async move {
-        do_async_push(key, elements, ListDirection::Left).await
+        do_async_push(&key, elements, ListDirection::Left).await
     })
 }
 
@@ -31,15 +31,15 @@ pub fn tikv_lrange(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
         return Err(RedisError::WrongArity);
     }
     let mut args = args.into_iter().skip(1);
-    let key = args.next_str()?;
+    let key = args.next_bytes()?;
     let start = args.next_str()?.parse::<i64>().unwrap();
     let end = args.next_str()?.parse::<i64>().unwrap();
     ctx.log_debug(&format!(
         "Handle tikv_lrange commands, key: {}, start: {}, end: {}",
-        key, start, end
+        String::from_utf8_lossy(&key), start, end
     ));
     async_execute(ctx, async move {
-        do_async_lrange(key, start, end).await
+        do_async_lrange(&key, start, end).await
     })
 }
 
@@ -50,14 +50,14 @@ pub fn tikv_rpush(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
         return Err(RedisError::WrongArity);
     }
     let mut args = args.into_iter().skip(1);
-    let key = args.next_str()?;
-    let elements = args.map(|x| x.to_string_lossy()).collect();
+    let key = args.next_bytes()?;
+    let elements: Vec<Vec<u8>> = args.map(|x| x.as_slice().to_vec()).collect();
     ctx.log_debug(&format!(
         "Handle tikv_lpush commands, key: {}, elements: {:?}",
-        key, elements
+        String::from_utf8_lossy(&key), elements
     ));
     async_execute(ctx, async move {
-        do_async_push(key, elements, ListDirection::Right).await
+        do_async_push(&key, elements, ListDirection::Right).await
     })
 }
 
@@ -68,10 +68,10 @@ pub fn tikv_llen(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
         return Err(RedisError::WrongArity);
     }
     let mut args = args.into_iter().skip(1);
-    let key = args.next_str()?;
-    ctx.log_debug(&format!("Handle tikv_llen commands, key: {}", key));
+    let key = args.next_bytes()?;
+    ctx.log_debug(&format!("Handle tikv_llen commands, key: {}", String::from_utf8_lossy(&key)));
     async_execute(ctx, async move {
-        do_async_llen(key).await
+        do_async_llen(&key).await
     })
 }
 
@@ -82,7 +82,7 @@ pub fn tikv_lpop(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
         return Err(RedisError::WrongArity);
     }
     let mut args = args.into_iter().skip(1);
-    let key = args.next_str()?;
+    let key = args.next_bytes()?;
     let count = match args.next() {
         Some(s) => i64::from_str(s.try_as_str()?)?,
         None => 1,
@@ -92,10 +92,10 @@ pub fn tikv_lpop(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
     }
     ctx.log_debug(&format!(
         "Handle tikv_lpop commands, key: {}, count: {}",
-        key, count
+        String::from_utf8_lossy(&key), count
     ));
     async_execute(ctx, async move {
-        do_async_pop(key, count, ListDirection::Left).await
+        do_async_pop(&key, count, ListDirection::Left).await
     })
 }
 
@@ -106,7 +106,7 @@ pub fn tikv_rpop(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
         return Err(RedisError::WrongArity);
     }
     let mut args = args.into_iter().skip(1);
-    let key = args.next_str()?;
+    let key = args.next_bytes()?;
     let count = match args.next() {
         Some(s) => i64::from_str(s.try_as_str()?)?,
         None => 1,
@@ -116,10 +116,10 @@ pub fn tikv_rpop(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
     }
     ctx.log_debug(&format!(
         "Handle tikv_lpop commands, key: {}, count: {}",
-        key, count
+        String::from_utf8_lossy(&key), count
     ));
     async_execute(ctx, async move {
-        do_async_pop(key, count, ListDirection::Right).await
+        do_async_pop(&key, count, ListDirection::Right).await
     })
 }
 
@@ -133,14 +133,14 @@ pub fn tikv_lindex(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
         return Err(RedisError::Str("extra arguments are not supported yet."));
     }
     let mut args = args.into_iter().skip(1);
-    let key = args.next_str()?;
+    let key = args.next_bytes()?;
     let index = args.next_i64()?;
     ctx.log_debug(&format!(
         "Handle tikv_lindex commands, key: {}, index: {}",
-        key, index
+        String::from_utf8_lossy(&key), index
     ));
     async_execute(ctx, async move {
-        do_async_lindex(key, index).await
+        do_async_lindex(&key, index).await
     })
 }
 
@@ -151,9 +151,9 @@ pub fn tikv_ldel(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
         return Err(RedisError::WrongArity);
     }
     let mut args = args.into_iter().skip(1);
-    let key = args.next_str()?;
+    let key = args.next_bytes()?;
     async_execute(ctx, async move {
-        do_async_ldel(key).await
+        do_async_ldel(&key).await
     })
 }
 
@@ -163,12 +163,12 @@ pub fn tikv_ltrim(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
         return Err(RedisError::WrongArity);
     }
     let mut args = args.into_iter().skip(1);
-    let key = args.next_str()?;
+    let key = args.next_bytes()?;
     let start = args.next_str()?.parse::<i64>().unwrap();
     let end = args.next_str()?.parse::<i64>().unwrap();
     ctx.log_debug(&format!(
         "Handle tikv_ltrim commands, key: {}, start: {}, end: {}",
-        key, start, end
+        String::from_utf8_lossy(&key), start, end
     ));
     let blocked_client = ctx.block_client();
     tokio_spawn(async move {
@@ -186,7 +186,7 @@ pub fn tikv_lpos(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
         return Err(RedisError::Str("extra arguments are not supported yet."));
     }
     let mut args = args.into_iter().skip(1);
-    let key = args.next_str()?;
+    let key = args.next_bytes()?;
     let element = args.next_str()?;
     ctx.log_debug(&format!(
         "Handle tikv_lpos commands, key: {}, element: {}",
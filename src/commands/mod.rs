@@ -1,6 +1,7 @@
 use std::collections::{HashMap, LinkedList};
 use std::sync::{Arc, Mutex, RwLock};
 use tikv_client::{RawClient, Transaction, TransactionClient};
+use crate::encoding::Compression;
 
 pub mod string;
 pub mod connection;
@@ -13,6 +14,10 @@ pub mod list;
 pub mod admin;
 pub mod set;
 pub mod mock;
+pub mod generic;
+pub mod bitmap;
+pub mod hyperloglog;
+pub mod counter;
 
 lazy_static! {
     pub static ref PD_ADDRS: Arc<RwLock<Option<Vec<String>>>> = Arc::new(RwLock::new(None));
@@ -20,13 +25,28 @@ lazy_static! {
         Arc::new(RwLock::new(HashMap::new()));
     pub static ref TIKV_TNX_CONN_POOL: Arc<Mutex<LinkedList<TransactionClient>>> =
         Arc::new(Mutex::new(LinkedList::new()));
+    pub static ref KEY_PREFIX: Arc<RwLock<Vec<u8>>> =
+        Arc::new(RwLock::new(DEFAULT_KEY_PREFIX.as_bytes().to_vec()));
 }
 
+pub const DEFAULT_KEY_PREFIX: &str = "x$R_";
+
 pub static mut TIKV_RAW_CLIENT: Option<RawClient> = None;
 pub static mut TIKV_RAW_CLIENT_2: Option<RawClient> = None;
 
 pub static mut INSTANCE_ID: u64 = 0;
 
+pub const DEFAULT_COMPRESS_THRESHOLD: usize = 4096;
+
+pub static mut COMPRESSION: Compression = Compression::None;
+pub static mut COMPRESS_THRESHOLD: usize = DEFAULT_COMPRESS_THRESHOLD;
+
+// Sharded counter spreads increments into at most `COUNTER_SHARDS` sub keys
+pub const DEFAULT_COUNTER_SHARDS: u32 = 16;
+pub const MAX_COUNTER_SHARDS: u32 = 1024;
+
+pub static mut COUNTER_SHARDS: u32 = DEFAULT_COUNTER_SHARDS;
+
 pub fn set_instance_id(id: u64) {
     unsafe {
         INSTANCE_ID = id;
@@ -37,15 +57,47 @@ pub fn get_instance_id() -> u64 {
     unsafe { INSTANCE_ID }
 }
 
+pub fn set_key_prefix(prefix: &[u8]) {
+    *KEY_PREFIX.write().unwrap() = prefix.to_vec();
+}
+
+pub fn get_key_prefix() -> Vec<u8> {
+    KEY_PREFIX.read().unwrap().clone()
+}
+
+pub fn set_compression(algorithm: Compression, threshold: usize) {
+    unsafe {
+        COMPRESSION = algorithm;
+        COMPRESS_THRESHOLD = threshold;
+    }
+}
+
+pub fn get_compression() -> (Compression, usize) {
+    unsafe { (COMPRESSION, COMPRESS_THRESHOLD) }
+}
+
+pub fn set_counter_shards(shards: u32) {
+    unsafe {
+        COUNTER_SHARDS = shards;
+    }
+}
+
+pub fn get_counter_shards() -> u32 {
+    unsafe { COUNTER_SHARDS }
+}
+
 pub use crate::commands::{
     connection::{tikv_connect, tikv_close},
     metrics::tikv_status,
     string::{
         tikv_raw_get, tikv_raw_set, tikv_raw_del, tikv_raw_setnx,
         tikv_raw_cached_del, tikv_raw_cached_get, tikv_raw_cached_set,
-        tikv_raw_incr, tikv_raw_incrby, tikv_raw_decr, tikv_raw_decrby,
+        tikv_raw_incr, tikv_raw_incrby, tikv_raw_decr, tikv_raw_decrby, tikv_raw_incrbyfloat,
         tikv_raw_exists, tikv_raw_batch_get, tikv_raw_batch_set,
         tikv_raw_scan, tikv_redis_set, tikv_raw_expire, tikv_raw_ttl,
+        tikv_raw_pexpire, tikv_raw_expireat, tikv_raw_pexpireat, tikv_raw_pttl, tikv_raw_persist,
+        tikv_raw_getset, tikv_raw_getdel, tikv_raw_getex,
+        tikv_raw_append, tikv_raw_strlen, tikv_raw_getrange, tikv_raw_setrange,
     },
     profiler::{
         tikv_profile_start, tikv_profile_finish,
@@ -53,6 +105,9 @@ pub use crate::commands::{
     hash::{
         tikv_hdel, tikv_hexists, tikv_hget, tikv_hget_all, tikv_hkeys,
         tikv_hmget, tikv_hmset, tikv_hset, tikv_hvals,
+        tikv_hlen, tikv_hsetnx, tikv_hincrby, tikv_hincrbyfloat, tikv_hstrlen,
+        tikv_hscan, tikv_hexpire, tikv_hpexpire, tikv_hexpireat, tikv_hpexpireat,
+        tikv_hpersist, tikv_httl, tikv_hpttl, tikv_hrandfield,
     },
     list::{
         tikv_lindex, tikv_llen, tikv_lrange, tikv_ldel,
@@ -60,6 +115,7 @@ pub use crate::commands::{
     },
     admin::{
         tikv_rawkv_cfscan, tikv_rawkv_dscan, tikv_rawkv_lscan, tikv_rawkv_wscan,
+        tikv_migrate, tikv_flushdb, tikv_swapdb, tikv_encode_key, tikv_decode_key,
     },
     set::{
         tikv_sadd, tikv_scard, tikv_smembers,
@@ -67,4 +123,16 @@ pub use crate::commands::{
     mock::{
         tikv_mock_get,
     },
+    generic::{
+        tikv_type,
+    },
+    bitmap::{
+        tikv_setbit, tikv_getbit, tikv_bitcount, tikv_bitpos, tikv_bitop,
+    },
+    hyperloglog::{
+        tikv_pfadd, tikv_pfcount, tikv_pfmerge,
+    },
+    counter::{
+        tikv_cincr, tikv_cgetcount,
+    },
 };
\ No newline at end of file
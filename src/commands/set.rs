@@ -1,9 +1,9 @@
 use crate::{
     metrics::*,
     commands::asyncs::set::*,
-    utils::async_execute,
+    utils::{async_execute, NextBytes},
 };
-use redis_module::{Context, NextArg, RedisError, RedisResult, RedisString};
+use redis_module::{Context, RedisError, RedisResult, RedisString};
 
 pub fn tikv_sadd(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
     REQUEST_COUNTER.inc();
@@ -12,10 +12,10 @@ pub fn tikv_sadd(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
         return Err(RedisError::WrongArity);
     }
     let mut args = args.into_iter().skip(1);
-    let key = args.next_str()?;
-    let members = args.map(|x| x.to_string_lossy()).collect();
+    let key = args.next_bytes()?;
+    let members = args.map(|x| x.as_slice().to_vec()).collect();
     async_execute(ctx, async move {
-        do_async_sadd(key, members).await
+        do_async_sadd(&key, members).await
     })
 }
 
@@ -26,9 +26,9 @@ pub fn tikv_scard(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
         return Err(RedisError::WrongArity);
     }
     let mut args = args.into_iter().skip(1);
-    let key = args.next_str()?;
+    let key = args.next_bytes()?;
     async_execute(ctx, async move {
-        do_async_scard(key).await
+        do_async_scard(&key).await
     })
 }
 
@@ -39,8 +39,8 @@ pub fn tikv_smembers(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
         return Err(RedisError::WrongArity);
     }
     let mut args = args.into_iter().skip(1);
-    let key = args.next_str()?;
+    let key = args.next_bytes()?;
     async_execute(ctx, async move {
-        do_async_smembers(key).await
+        do_async_smembers(&key).await
     })
 }
\ No newline at end of file
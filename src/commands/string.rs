@@ -1,8 +1,11 @@
 use redis_module::{Context, RedisString, RedisResult, RedisError, NextArg, RedisValue, ThreadSafeContext, KeyType};
-use tikv_client::KvPair;
 use crate::{
     metrics::*,
-    utils::{tokio_spawn, redis_resp, redis_resp_with_ctx, resp_int, async_execute}, encoding::KeyEncoder,
+    encoding::now_millis,
+    utils::{
+        tokio_spawn, redis_resp, redis_resp_with_ctx, resp_int, async_execute, with_selected_db,
+        parse_redis_float, NextBytes,
+    },
 };
 use super::asyncs::string::*;
 
@@ -13,9 +16,9 @@ pub fn tikv_raw_get(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
         return Err(RedisError::WrongArity);
     }
     let mut args = args.into_iter().skip(1);
-    let key = args.next_str()?;
+    let key = args.next_bytes()?;
     async_execute(ctx, async move {
-        do_async_rawkv_get(key).await
+        do_async_rawkv_get(&key).await
     })
 }
 
@@ -26,10 +29,113 @@ pub fn tikv_raw_set(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
         return Err(RedisError::WrongArity);
     }
     let mut args = args.into_iter().skip(1);
-    let key = args.next_str()?;
-    let value = args.next_str()?;
+    let key = args.next_bytes()?;
+    let value = args.next_bytes()?;
+    let opts = parse_set_options(args)?;
     async_execute(ctx, async move {
-        do_async_rawkv_put(key, value).await
+        do_async_rawkv_set(&key, &value, opts).await
+    })
+}
+
+// Parse `[NX | XX] [GET] [EX seconds | PX milliseconds | EXAT unix-time-seconds |
+// PXAT unix-time-milliseconds | KEEPTTL]`
+fn parse_set_options<T: Iterator<Item = RedisString>>(mut args: T) -> Result<SetOptions, RedisError> {
+    let mut opts = SetOptions::default();
+    let mut has_expire = false;
+    while let Some(arg) = args.next() {
+        let opt = arg.try_as_str()?.to_uppercase();
+        match opt.as_str() {
+            "NX" if !opts.xx => opts.nx = true,
+            "XX" if !opts.nx => opts.xx = true,
+            "GET" => opts.get = true,
+            "KEEPTTL" if !has_expire => {
+                opts.keep_ttl = true;
+                has_expire = true;
+            }
+            "EX" | "PX" | "EXAT" | "PXAT" if !has_expire => {
+                opts.expire_at = Some(parse_expire_at(&opt, &mut args, "set")?);
+                has_expire = true;
+            }
+            _ => return Err(RedisError::Str("ERR syntax error")),
+        }
+    }
+    Ok(opts)
+}
+
+// Parse value of EX, PX, EXAT or PXAT option into unix timestamp in milliseconds
+fn parse_expire_at<T: Iterator<Item = RedisString>>(
+    opt: &str,
+    args: &mut T,
+    cmd: &str,
+) -> Result<u64, RedisError> {
+    let invalid_err = || RedisError::String(format!("ERR invalid expire time in '{}' command", cmd));
+    let val = args.next_i64()?;
+    if val <= 0 {
+        return Err(invalid_err());
+    }
+    let val = val as u64;
+    let expire_at = match opt {
+        "EX" => val.checked_mul(1000).and_then(|v| v.checked_add(now_millis())),
+        "PX" => val.checked_add(now_millis()),
+        "EXAT" => val.checked_mul(1000),
+        _ => Some(val),
+    };
+    expire_at.ok_or_else(invalid_err)
+}
+
+pub fn tikv_raw_getset(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    REQUEST_COUNTER.inc();
+    REQUEST_CMD_COUNTER.with_label_values(&["getset"]).inc();
+    if args.len() != 3 {
+        return Err(RedisError::WrongArity);
+    }
+    let mut args = args.into_iter().skip(1);
+    let key = args.next_bytes()?;
+    let value = args.next_bytes()?;
+    let opts = SetOptions {
+        get: true,
+        ..Default::default()
+    };
+    async_execute(ctx, async move {
+        do_async_rawkv_set(&key, &value, opts).await
+    })
+}
+
+pub fn tikv_raw_getdel(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    REQUEST_COUNTER.inc();
+    REQUEST_CMD_COUNTER.with_label_values(&["getdel"]).inc();
+    if args.len() != 2 {
+        return Err(RedisError::WrongArity);
+    }
+    let mut args = args.into_iter().skip(1);
+    let key = args.next_bytes()?;
+    async_execute(ctx, async move {
+        do_async_rawkv_getdel(&key).await
+    })
+}
+
+pub fn tikv_raw_getex(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    REQUEST_COUNTER.inc();
+    REQUEST_CMD_COUNTER.with_label_values(&["getex"]).inc();
+    if args.len() < 2 {
+        return Err(RedisError::WrongArity);
+    }
+    let mut args = args.into_iter().skip(1);
+    let key = args.next_bytes()?;
+    // None means keep current expire time, Some(None) means remove it
+    let mut expire: Option<Option<u64>> = None;
+    while let Some(arg) = args.next() {
+        let opt = arg.try_as_str()?.to_uppercase();
+        match opt.as_str() {
+            "PERSIST" if expire.is_none() => expire = Some(None),
+            "EX" | "PX" | "EXAT" | "PXAT" if expire.is_none() => {
+                expire = Some(Some(parse_expire_at(&opt, &mut args, "getex")?));
+            }
+            _ => return Err(RedisError::Str("ERR syntax error")),
+        }
+    }
+    async_execute(ctx, async move {
+        do_async_rawkv_getex(&key, expire).await
     })
 }
 
@@ -39,7 +145,7 @@ pub fn tikv_raw_del(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
     if args.len() < 2 {
         return Err(RedisError::WrongArity);
     }
-    let keys: Vec<String> = args.into_iter().skip(1).map(|s| s.to_string()).collect();
+    let keys: Vec<Vec<u8>> = args.into_iter().skip(1).map(|s| s.as_slice().to_vec()).collect();
     async_execute(ctx, async move {
         do_async_rawkv_batch_del(keys).await
     })
@@ -53,19 +159,19 @@ pub fn tikv_raw_scan(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
     }
     let num_args = args.len();
     let mut args = args.into_iter().skip(1);
-    let start_key = args.next_str()?;
-    let end_key: &str;
+    let start_key = args.next_bytes()?;
+    let end_key: Vec<u8>;
     if num_args > 3 {
-        end_key = args.next_str()?;
+        end_key = args.next_bytes()?;
     } else {
-        end_key = "";
+        end_key = Vec::new();
     }
     let limit = args.next_u64()?;
     async_execute(ctx, async move {
         if num_args == 3 {
-            do_async_rawkv_scan(start_key, limit).await
+            do_async_rawkv_scan(&start_key, limit).await
         } else {
-            do_async_rawkv_scan_range(start_key, end_key, limit).await
+            do_async_rawkv_scan_range(&start_key, &end_key, limit).await
         }
     })
 }
@@ -77,10 +183,10 @@ pub fn tikv_raw_setnx(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
         return Err(RedisError::WrongArity);
     }
     let mut args = args.into_iter().skip(1);
-    let key = args.next_str()?;
-    let value = args.next_str()?;
+    let key = args.next_bytes()?;
+    let value = args.next_bytes()?;
     async_execute(ctx, async move {
-        do_async_rawkv_put_not_exists(key, value).await
+        do_async_rawkv_put_not_exists(&key, &value).await
     })
 }
 
@@ -104,12 +210,12 @@ pub fn tikv_raw_cached_get(ctx: &Context, args: Vec<RedisString>) -> RedisResult
         }
     }
     let blocked_client = ctx.block_client();
-    let skey = key.to_string_lossy();
-    tokio_spawn(async move {
+    let skey = key.as_slice().to_vec();
+    tokio_spawn(with_selected_db(ctx, async move {
         let tctx = ThreadSafeContext::with_blocked_client(blocked_client);
         let res = do_async_rawkv_cached_get(&tctx, skey).await;
         redis_resp_with_ctx(&tctx, res);
-    });
+    }));
     Ok(RedisValue::NoReply)
 }
 
@@ -120,15 +226,31 @@ pub fn tikv_raw_cached_set(ctx: &Context, args: Vec<RedisString>) -> RedisResult
         return Err(RedisError::WrongArity);
     }
     let mut args = args.into_iter().skip(1);
-    let key = args.next_str()?;
-    let value = args.next_str()?;
+    let rkey = args.next_arg()?;
+    let key = rkey.as_slice().to_vec();
+    let value = args.next_bytes()?;
+    let opts = parse_set_options(args)?;
 
+    // Cached value has no expire time and NX, XX and GET depend on the value
+    // in TiKV, so SET with options removes cached value and writes TiKV only.
+    if !opts.is_plain() {
+        if let Err(err) = ctx.open_key_writable(&rkey).delete() {
+            ctx.log_notice(&format!(
+                "Delete Redis Key {} got error: {}",
+                rkey.to_string(),
+                err.to_string()
+            ));
+        }
+        return async_execute(ctx, async move {
+            do_async_rawkv_set(&key, &value, opts).await
+        });
+    }
     let blocked_client = ctx.block_client();
-    tokio_spawn(async move {
+    tokio_spawn(with_selected_db(ctx, async move {
         let tctx = ThreadSafeContext::with_blocked_client(blocked_client);
-        let res = do_async_rawkv_cached_put(&tctx, key.to_string(), value).await;
+        let res = do_async_rawkv_cached_put(&tctx, &key, &value).await;
         redis_resp_with_ctx(&tctx, res);
-    });
+    }));
     Ok(RedisValue::NoReply)
 }
 
@@ -139,9 +261,9 @@ pub fn tikv_raw_cached_del(ctx: &Context, args: Vec<RedisString>) -> RedisResult
         return Err(RedisError::WrongArity);
     }
     let args = args.into_iter().skip(1);
-    let mut keys: Vec<String> = Vec::new();
+    let mut keys: Vec<Vec<u8>> = Vec::new();
     args.for_each(|s| {
-        keys.push(s.to_string());
+        keys.push(s.as_slice().to_vec());
         let rkey = ctx.open_key_writable(&s);
         match rkey.delete() {
             Err(err) => {
@@ -155,10 +277,10 @@ pub fn tikv_raw_cached_del(ctx: &Context, args: Vec<RedisString>) -> RedisResult
         }
     });
     let blocked_client = ctx.block_client();
-    tokio_spawn(async move {
+    tokio_spawn(with_selected_db(ctx, async move {
         let res = do_async_rawkv_batch_del(keys).await;
         redis_resp(blocked_client, res);
-    });
+    }));
     Ok(RedisValue::NoReply)
 }
 
@@ -169,9 +291,9 @@ pub fn tikv_raw_incr(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
         return Err(RedisError::WrongArity);
     }
     let mut args = args.into_iter().skip(1);
-    let key = args.next_str()?;
+    let key = args.next_bytes()?;
     async_execute(ctx, async move {
-        do_async_rawkv_incr(key.to_owned(), true, 1).await
+        do_async_rawkv_incr(&key, true, 1).await
     })
 }
 
@@ -182,9 +304,9 @@ pub fn tikv_raw_decr(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
         return Err(RedisError::WrongArity);
     }
     let mut args = args.into_iter().skip(1);
-    let key = args.next_str()?;
+    let key = args.next_bytes()?;
     async_execute(ctx, async move {
-        do_async_rawkv_incr(key.to_owned(), false, 1).await
+        do_async_rawkv_incr(&key, false, 1).await
     })
 }
 
@@ -195,10 +317,10 @@ pub fn tikv_raw_incrby(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
         return Err(RedisError::WrongArity);
     }
     let mut args = args.into_iter().skip(1);
-    let key = args.next_str()?;
+    let key = args.next_bytes()?;
     let step = args.next_i64()?;
     async_execute(ctx, async move {
-        do_async_rawkv_incr(key.to_owned(), true, step).await
+        do_async_rawkv_incr(&key, true, step).await
     })
 }
 
@@ -209,10 +331,27 @@ pub fn tikv_raw_decrby(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
         return Err(RedisError::WrongArity);
     }
     let mut args = args.into_iter().skip(1);
-    let key = args.next_str()?;
+    let key = args.next_bytes()?;
     let step = args.next_i64()?;
     async_execute(ctx, async move {
-        do_async_rawkv_incr(key.to_owned(), false, step).await
+        do_async_rawkv_incr(&key, false, step).await
+    })
+}
+
+pub fn tikv_raw_incrbyfloat(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    REQUEST_COUNTER.inc();
+    REQUEST_CMD_COUNTER.with_label_values(&["incrbyfloat"]).inc();
+    if args.len() != 3 {
+        return Err(RedisError::WrongArity);
+    }
+    let mut args = args.into_iter().skip(1);
+    let key = args.next_bytes()?;
+    let step = match parse_redis_float(&args.next_bytes()?) {
+        Some(val) => val,
+        None => return Err(RedisError::Str("ERR value is not a valid float")),
+    };
+    async_execute(ctx, async move {
+        do_async_rawkv_incr_float(&key, step).await
     })
 }
 
@@ -222,10 +361,10 @@ pub fn tikv_raw_exists(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
     if args.len() < 2 {
         return Err(RedisError::WrongArity);
     }
-    let keys: Vec<String> = args
+    let keys: Vec<Vec<u8>> = args
         .into_iter()
         .skip(1)
-        .map(|s| s.to_string())
+        .map(|s| s.as_slice().to_vec())
         .collect();
     
     async_execute(ctx, async move {
@@ -239,10 +378,10 @@ pub fn tikv_raw_batch_get(ctx: &Context, args: Vec<RedisString>) -> RedisResult
     if args.len() < 2 {
         return Err(RedisError::WrongArity);
     }
-    let keys: Vec<String> = args
+    let keys: Vec<Vec<u8>> = args
         .into_iter()
         .skip(1)
-        .map(|s| s.to_string())
+        .map(|s| s.as_slice().to_vec())
         .collect();
     
     async_execute(ctx, async move {
@@ -257,14 +396,12 @@ pub fn tikv_raw_batch_set(ctx: &Context, args: Vec<RedisString>) -> RedisResult
     if num_kvs % 2 != 0 {
         return Err(RedisError::WrongArity);
     }
-    let mut kvs: Vec<KvPair> = Vec::new();
-    let encoder = KeyEncoder::new();
+    let mut kvs: Vec<(Vec<u8>, Vec<u8>)> = Vec::new();
     let mut args = args.into_iter().skip(1);
     for _i in 0..num_kvs / 2 {
-        let key = args.next_str()?;
-        let value = args.next_str()?;
-        let kv = KvPair::from((encoder.encode_string(key), value.to_owned()));
-        kvs.push(kv);
+        let key = args.next_bytes()?;
+        let value = args.next_bytes()?;
+        kvs.push((key, value));
     }
     async_execute(ctx, async move {
         do_async_rawkv_batch_put(kvs).await
@@ -277,31 +414,189 @@ pub fn tikv_redis_set(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
     }
     let mut args = args.into_iter().skip(1);
     let key = args.next_arg()?;
-    let value = args.next_str()?;
-    let rkey = ctx.open_key_writable(&key);
-    let _ = rkey.write(value)?;
+    let value = args.next_arg()?;
+    // Go through SET so binary values are stored as is
+    ctx.call("SET", &[key.as_slice(), value.as_slice()])?;
     Ok(resp_int(1))
 }
 
-pub fn tikv_raw_expire(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+// Parse `[NX | XX | GT | LT]` condition of EXPIRE series commands
+pub fn parse_expire_condition<T: Iterator<Item = RedisString>>(args: T) -> Result<ExpireCondition, RedisError> {
+    let mut cond = ExpireCondition::default();
+    for arg in args {
+        match arg.try_as_str()?.to_uppercase().as_str() {
+            "NX" => cond.nx = true,
+            "XX" => cond.xx = true,
+            "GT" => cond.gt = true,
+            "LT" => cond.lt = true,
+            opt => return Err(RedisError::String(format!("ERR Unsupported option {}", opt))),
+        };
+    }
+    if cond.nx && (cond.xx || cond.gt || cond.lt) {
+        return Err(RedisError::Str("ERR NX and XX, GT or LT options at the same time are not compatible"));
+    }
+    if cond.gt && cond.lt {
+        return Err(RedisError::Str("ERR GT and LT options at the same time are not compatible"));
+    }
+    Ok(cond)
+}
+
+// Convert expire time argument into unix timestamp in milliseconds. Time is in
+// milliseconds if `in_ms` is true, and is unix timestamp if `absolute` is true.
+pub fn parse_expire_time(val: i64, cmd: &str, in_ms: bool, absolute: bool) -> Result<u64, RedisError> {
+    let invalid_err = || RedisError::String(format!("ERR invalid expire time in '{}' command", cmd));
+    let ms = if in_ms { Some(val) } else { val.checked_mul(1000) }.ok_or_else(invalid_err)?;
+    let expire_at = if absolute {
+        ms
+    } else {
+        ms.checked_add(now_millis() as i64).ok_or_else(invalid_err)?
+    };
+    // Expire time passed, key will be deleted
+    Ok(expire_at.max(0) as u64)
+}
+
+// Parse expire time and condition of EXPIRE series commands
+fn parse_expire_args<T: Iterator<Item = RedisString>>(
+    mut args: T,
+    cmd: &str,
+    in_ms: bool,
+    absolute: bool,
+) -> Result<(u64, ExpireCondition), RedisError> {
+    let val = args.next_i64()?;
+    let cond = parse_expire_condition(args)?;
+    Ok((parse_expire_time(val, cmd, in_ms, absolute)?, cond))
+}
+
+fn expire_command(
+    ctx: &Context,
+    args: Vec<RedisString>,
+    cmd: &str,
+    in_ms: bool,
+    absolute: bool,
+) -> RedisResult {
+    REQUEST_COUNTER.inc();
+    REQUEST_CMD_COUNTER.with_label_values(&[cmd]).inc();
     if args.len() < 3 {
         return Err(RedisError::WrongArity);
     }
     let mut args = args.into_iter().skip(1);
-    let key = args.next_str()?;
-    let ttl = args.next_u64()?;
+    let key = args.next_bytes()?;
+    let (expire_at, cond) = parse_expire_args(args, cmd, in_ms, absolute)?;
+    async_execute(ctx, async move {
+        do_async_rawkv_expire_at(&key, expire_at, cond).await
+    })
+}
+
+pub fn tikv_raw_expire(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    expire_command(ctx, args, "expire", false, false)
+}
+
+pub fn tikv_raw_pexpire(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    expire_command(ctx, args, "pexpire", true, false)
+}
+
+pub fn tikv_raw_expireat(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    expire_command(ctx, args, "expireat", false, true)
+}
+
+pub fn tikv_raw_pexpireat(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    expire_command(ctx, args, "pexpireat", true, true)
+}
+
+pub fn tikv_raw_persist(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    REQUEST_COUNTER.inc();
+    REQUEST_CMD_COUNTER.with_label_values(&["persist"]).inc();
+    if args.len() != 2 {
+        return Err(RedisError::WrongArity);
+    }
+    let mut args = args.into_iter().skip(1);
+    let key = args.next_bytes()?;
     async_execute(ctx, async move {
-        do_async_rawkv_expire(key.to_owned(), ttl).await
+        do_async_rawkv_persist(&key).await
+    })
+}
+
+fn ttl_command(ctx: &Context, args: Vec<RedisString>, cmd: &str, in_ms: bool) -> RedisResult {
+    REQUEST_COUNTER.inc();
+    REQUEST_CMD_COUNTER.with_label_values(&[cmd]).inc();
+    if args.len() != 2 {
+        return Err(RedisError::WrongArity);
+    }
+    let mut args = args.into_iter().skip(1);
+    let key = args.next_bytes()?;
+    async_execute(ctx, async move {
+        do_async_rawkv_get_ttl(&key, in_ms).await
     })
 }
 
 pub fn tikv_raw_ttl(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
-    if args.len() < 2 {
+    ttl_command(ctx, args, "ttl", false)
+}
+
+pub fn tikv_raw_pttl(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    ttl_command(ctx, args, "pttl", true)
+}
+
+pub fn tikv_raw_append(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    REQUEST_COUNTER.inc();
+    REQUEST_CMD_COUNTER.with_label_values(&["append"]).inc();
+    if args.len() != 3 {
+        return Err(RedisError::WrongArity);
+    }
+    let mut args = args.into_iter().skip(1);
+    let key = args.next_bytes()?;
+    let value = args.next_bytes()?;
+    async_execute(ctx, async move {
+        do_async_rawkv_append(&key, &value).await
+    })
+}
+
+pub fn tikv_raw_strlen(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    REQUEST_COUNTER.inc();
+    REQUEST_CMD_COUNTER.with_label_values(&["strlen"]).inc();
+    if args.len() != 2 {
+        return Err(RedisError::WrongArity);
+    }
+    let mut args = args.into_iter().skip(1);
+    let key = args.next_bytes()?;
+    async_execute(ctx, async move {
+        do_async_rawkv_strlen(&key).await
+    })
+}
+
+pub fn tikv_raw_getrange(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    REQUEST_COUNTER.inc();
+    REQUEST_CMD_COUNTER.with_label_values(&["getrange"]).inc();
+    if args.len() != 4 {
         return Err(RedisError::WrongArity);
     }
     let mut args = args.into_iter().skip(1);
-    let key = args.next_str()?;
+    let key = args.next_bytes()?;
+    let start = args.next_i64()?;
+    let end = args.next_i64()?;
     async_execute(ctx, async move {
-        do_async_rawkv_get_ttl(key.to_owned()).await
+        do_async_rawkv_getrange(&key, start, end).await
     })
-}
\ No newline at end of file
+}
+
+pub fn tikv_raw_setrange(ctx: &Context, args: Vec<RedisString>) -> RedisResult {
+    REQUEST_COUNTER.inc();
+    REQUEST_CMD_COUNTER.with_label_values(&["setrange"]).inc();
+    if args.len() != 4 {
+        return Err(RedisError::WrongArity);
+    }
+    let mut args = args.into_iter().skip(1);
+    let key = args.next_bytes()?;
+    let offset = args.next_i64()?;
+    let value = args.next_bytes()?;
+    if offset < 0 {
+        return Err(RedisError::Str("ERR offset is out of range"));
+    }
+    let offset = offset as usize;
+    if !value.is_empty() && offset + value.len() > MAX_STRING_SIZE {
+        return Err(RedisError::Str("ERR string exceeds maximum allowed size (proto-max-bulk-len)"));
+    }
+    async_execute(ctx, async move {
+        do_async_rawkv_setrange(&key, offset, &value).await
+    })
+}
@@ -1,21 +1,268 @@
 use tikv_client::Key;
+use crate::commands::{get_key_prefix, DEFAULT_KEY_PREFIX};
+use super::{DataType, ENC_GROUP_SIZE, ENC_MARKER, FORMAT_VERSION};
 
+// Length of `[INSTANCE_ID]_[VERSION][DB][DATATYPE]` after key prefix
+const HEADER_LEN_WITHOUT_PREFIX: usize = 15;
 
-pub struct KeyDecoder {}
+// Length of format version 0 header `x$R_[INSTANCE_ID]_[DATATYPE]`
+const LEGACY_HEADER_LEN: usize = DEFAULT_KEY_PREFIX.len() + 10;
+
+// Key parts decoded from format version 0 layout
+pub enum LegacyKey {
+    String(Vec<u8>),
+    Hash(Vec<u8>, Vec<u8>),
+    ListMeta(Vec<u8>),
+    ListElem(Vec<u8>, i64),
+    Set(Vec<u8>, Vec<u8>),
+}
+
+// Key parts decoded from any RedisTiKV key, used by debug commands
+#[derive(Debug, PartialEq, Eq)]
+pub enum DecodedKey {
+    String(Vec<u8>),
+    StringChunk(Vec<u8>, u64, u64),
+    Hash(Vec<u8>, Vec<u8>),
+    HashMeta(Vec<u8>),
+    ListMeta(Vec<u8>),
+    ListElem(Vec<u8>, i64),
+    Set(Vec<u8>, Vec<u8>),
+    CounterShard(Vec<u8>, u32),
+    Type(Vec<u8>),
+    Meta(Vec<u8>),
+}
+
+impl From<LegacyKey> for DecodedKey {
+    fn from(key: LegacyKey) -> Self {
+        match key {
+            LegacyKey::String(k) => DecodedKey::String(k),
+            LegacyKey::Hash(k, f) => DecodedKey::Hash(k, f),
+            LegacyKey::ListMeta(k) => DecodedKey::ListMeta(k),
+            LegacyKey::ListElem(k, i) => DecodedKey::ListElem(k, i),
+            LegacyKey::Set(k, m) => DecodedKey::Set(k, m),
+        }
+    }
+}
+
+pub struct KeyInfo {
+    pub instance_id: u64,
+    pub version: u8,
+    // DB is not recorded in metadata keys and legacy keys
+    pub db: Option<u32>,
+    pub key: DecodedKey,
+}
+
+// Decode memcomparable bytes generated by `encode_bytes`. Returns the origin
+// data and the number of bytes consumed, or None if data is malformed.
+pub fn decode_bytes(data: &[u8]) -> Option<(Vec<u8>, usize)> {
+    let mut ret: Vec<u8> = Vec::new();
+    let mut offset = 0;
+    loop {
+        if data.len() < offset + ENC_GROUP_SIZE + 1 {
+            return None;
+        }
+        let group = &data[offset..offset + ENC_GROUP_SIZE];
+        let marker = data[offset + ENC_GROUP_SIZE];
+        offset += ENC_GROUP_SIZE + 1;
+        let pad = (ENC_MARKER - marker) as usize;
+        if pad == 0 {
+            ret.extend_from_slice(group);
+            continue;
+        }
+        if pad > ENC_GROUP_SIZE {
+            return None;
+        }
+        ret.extend_from_slice(&group[..ENC_GROUP_SIZE - pad]);
+        return Some((ret, offset));
+    }
+}
+
+pub struct KeyDecoder {
+    header_len: usize,
+}
 
 impl KeyDecoder {
     pub fn new() -> Self {
-        KeyDecoder{}
+        KeyDecoder{
+            header_len: get_key_prefix().len() + HEADER_LEN_WITHOUT_PREFIX,
+        }
+    }
+
+    // Split `..._D_[KEY]_[SUBKEY]` into user key and the sub key part
+    fn decode_data_key(&self, rkey: Key) -> (Vec<u8>, Vec<u8>) {
+        let bytes: Vec<u8> = rkey.into();
+        let start = self.header_len + 3;
+        if bytes.len() < start {
+            return (Vec::new(), Vec::new());
+        }
+        match decode_bytes(&bytes[start..]) {
+            Some((key, used)) => {
+                let sub_start = usize::min(start + used + 1, bytes.len());
+                (key, bytes[sub_start..].to_vec())
+            }
+            None => (Vec::new(), bytes[start..].to_vec()),
+        }
+    }
+
+    // Split legacy `[KEY]_[SUBKEY]`. Key is not encoded in format version 0, so
+    // data with more than one `_` can not be split exactly and returns None.
+    fn split_legacy_data_key(&self, data: &[u8]) -> Option<(Vec<u8>, Vec<u8>)> {
+        let pos = data.iter().position(|b| *b == b'_')?;
+        if data[pos + 1..].contains(&b'_') {
+            return None;
+        }
+        Some((data[..pos].to_vec(), data[pos + 1..].to_vec()))
+    }
+
+    pub fn decode_legacy_key(&self, rkey: Key) -> Option<LegacyKey> {
+        let bytes: Vec<u8> = rkey.into();
+        if bytes.len() < LEGACY_HEADER_LEN + 1 {
+            return None;
+        }
+        let tp = bytes[LEGACY_HEADER_LEN - 1];
+        let data = &bytes[LEGACY_HEADER_LEN + 1..];
+        match (tp, data.get(..2)) {
+            (b'R', _) => Some(LegacyKey::String(data.to_vec())),
+            (b'H', Some(b"D_")) => {
+                let (key, field) = self.split_legacy_data_key(&data[2..])?;
+                Some(LegacyKey::Hash(key, field))
+            }
+            (b'S', Some(b"D_")) => {
+                let (key, member) = self.split_legacy_data_key(&data[2..])?;
+                Some(LegacyKey::Set(key, member))
+            }
+            (b'L', Some(b"M_")) => Some(LegacyKey::ListMeta(data[2..].to_vec())),
+            (b'L', Some(b"D_")) => {
+                let data = &data[2..];
+                if data.len() < 9 || data[data.len() - 9] != b'_' {
+                    return None;
+                }
+                let idx = i64::from_be_bytes(data[data.len() - 8..].try_into().unwrap());
+                Some(LegacyKey::ListElem(data[..data.len() - 9].to_vec(), idx))
+            }
+            _ => None,
+        }
+    }
+
+    // Decode `[ENCODED_KEY]_[SUBKEY]` part of data key
+    fn decode_key_and_sub(&self, data: &[u8]) -> Option<(Vec<u8>, Vec<u8>)> {
+        let (key, used) = decode_bytes(data)?;
+        match data.get(used) {
+            Some(b'_') => Some((key, data[used + 1..].to_vec())),
+            _ => None,
+        }
+    }
+
+    // Decode all parts of a raw TiKV key written by current or legacy format.
+    // Returns None if key is not a RedisTiKV key.
+    pub fn decode_key(&self, rkey: &[u8]) -> Option<KeyInfo> {
+        let prefix = get_key_prefix();
+        let decoded = if rkey.starts_with(&prefix) {
+            self.decode_current_key(&rkey[prefix.len()..])
+        } else {
+            None
+        };
+        decoded.or_else(|| {
+            if !rkey.starts_with(DEFAULT_KEY_PREFIX.as_bytes()) {
+                return None;
+            }
+            let inst = rkey.get(DEFAULT_KEY_PREFIX.len()..DEFAULT_KEY_PREFIX.len() + 8)?;
+            let key = self.decode_legacy_key(rkey.to_vec().into())?;
+            Some(KeyInfo {
+                instance_id: u64::from_be_bytes(inst.try_into().unwrap()),
+                version: 0,
+                db: None,
+                key: key.into(),
+            })
+        })
+    }
+
+    // Decode key after key prefix: `[INSTANCE_ID]_[VERSION][DB][DATATYPE]...`
+    // or metadata key `[INSTANCE_ID]_M_[NAME]`
+    fn decode_current_key(&self, data: &[u8]) -> Option<KeyInfo> {
+        if data.len() < 10 || data[8] != b'_' {
+            return None;
+        }
+        let instance_id = u64::from_be_bytes(data[..8].try_into().unwrap());
+        let data = &data[9..];
+        if data.starts_with(b"M_") {
+            return Some(KeyInfo {
+                instance_id,
+                version: FORMAT_VERSION,
+                db: None,
+                key: DecodedKey::Meta(data[2..].to_vec()),
+            });
+        }
+        if data.len() < 7 || data[0] != FORMAT_VERSION {
+            return None;
+        }
+        let db = u32::from_be_bytes(data[1..5].try_into().unwrap());
+        let tp = data[5];
+        let rest = &data[6..];
+        let key = match (tp, rest.get(..3)) {
+            (b'R', _) if rest.starts_with(b"C_") => {
+                let (key, sub) = self.decode_key_and_sub(&rest[2..])?;
+                if sub.len() != 16 {
+                    return None;
+                }
+                let gen = u64::from_be_bytes(sub[..8].try_into().unwrap());
+                let idx = u64::from_be_bytes(sub[8..].try_into().unwrap());
+                DecodedKey::StringChunk(key, gen, idx)
+            }
+            (b'R', _) if rest[0] == b'_' => DecodedKey::String(decode_bytes(&rest[1..])?.0),
+            (b'T', _) if rest[0] == b'_' => DecodedKey::Type(decode_bytes(&rest[1..])?.0),
+            (b'H', Some(b"_D_")) => {
+                let (key, field) = self.decode_key_and_sub(&rest[3..])?;
+                DecodedKey::Hash(key, field)
+            }
+            (b'H', Some(b"_M_")) => DecodedKey::HashMeta(decode_bytes(&rest[3..])?.0),
+            (b'S', Some(b"_D_")) => {
+                let (key, member) = self.decode_key_and_sub(&rest[3..])?;
+                DecodedKey::Set(key, member)
+            }
+            (b'C', Some(b"_D_")) => {
+                let (key, shard) = self.decode_key_and_sub(&rest[3..])?;
+                let shard: [u8; 4] = shard.try_into().ok()?;
+                DecodedKey::CounterShard(key, u32::from_be_bytes(shard))
+            }
+            (b'L', Some(b"_M_")) => DecodedKey::ListMeta(decode_bytes(&rest[3..])?.0),
+            (b'L', Some(b"_D_")) => {
+                let (key, idx) = self.decode_key_and_sub(&rest[3..])?;
+                let idx: [u8; 8] = idx.try_into().ok()?;
+                DecodedKey::ListElem(key, i64::from_be_bytes(idx))
+            }
+            _ => return None,
+        };
+        Some(KeyInfo {
+            instance_id,
+            version: FORMAT_VERSION,
+            db: Some(db),
+            key,
+        })
+    }
+
+    pub fn decode_type(&self, value: &[u8]) -> Option<DataType> {
+        match value.first() {
+            Some(b'R') => Some(DataType::String),
+            Some(b'H') => Some(DataType::Hash),
+            Some(b'L') => Some(DataType::List),
+            Some(b'S') => Some(DataType::Set),
+            Some(b'C') => Some(DataType::Counter),
+            _ => None,
+        }
     }
 
     pub fn decode_string(&self, key: Key) -> Vec<u8> {
-        let mut bytes: Vec<u8> = key.clone().into();
-        bytes.drain(15..).collect()
+        let bytes: Vec<u8> = key.into();
+        let start = usize::min(self.header_len + 1, bytes.len());
+        match decode_bytes(&bytes[start..]) {
+            Some((key, _)) => key,
+            None => bytes[start..].to_vec(),
+        }
     }
 
-    pub fn decode_hash_field(&self, rkey: Key, key: &str) -> Vec<u8> {
-        let mut bytes: Vec<u8> = rkey.clone().into();
-        bytes.drain(17 + key.len() + 1..).collect()
+    pub fn decode_hash_field(&self, rkey: Key) -> Vec<u8> {
+        self.decode_data_key(rkey).1
     }
 
     pub fn decode_list_meta(&self, value: Option<Vec<u8>>) -> (i64, i64) {
@@ -28,8 +275,200 @@ impl KeyDecoder {
         }
     }
 
-    pub fn decode_set_member(&self, rkey: Key, key: &str) -> Vec<u8> {
-        let mut bytes: Vec<u8> = rkey.clone().into();
-        bytes.drain(17 + key.len() + 1..).collect()
+    pub fn decode_set_member(&self, rkey: Key) -> Vec<u8> {
+        self.decode_data_key(rkey).1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoding::{encode_bytes, KeyEncoder, META_FORMAT_VERSION};
+
+    const INSTANCE_ID: u64 = 42;
+    const DB: u32 = 3;
+
+    fn encoder() -> KeyEncoder {
+        KeyEncoder {
+            key_prefix: get_key_prefix(),
+            instance_id: INSTANCE_ID.to_be_bytes(),
+            db: DB,
+        }
+    }
+
+    fn decode(rkey: Key) -> KeyInfo {
+        let bytes: Vec<u8> = rkey.into();
+        let info = KeyDecoder::new().decode_key(&bytes).unwrap();
+        assert_eq!(info.instance_id, INSTANCE_ID);
+        info
+    }
+
+    fn decode_data(rkey: Key) -> DecodedKey {
+        let info = decode(rkey);
+        assert_eq!(info.version, FORMAT_VERSION);
+        assert_eq!(info.db, Some(DB));
+        info.key
+    }
+
+    #[test]
+    fn test_bytes_round_trip() {
+        let cases: Vec<Vec<u8>> = vec![
+            vec![],
+            b"a".to_vec(),
+            b"1234567".to_vec(),
+            b"12345678".to_vec(),
+            b"123456789".to_vec(),
+            b"1234567812345678".to_vec(),
+            vec![0; 8],
+            vec![0xFF; 16],
+            b"key_with_\x00_and_\xFF".to_vec(),
+        ];
+        for data in cases {
+            let encoded = encode_bytes(&data);
+            assert_eq!(encoded.len(), (data.len() / ENC_GROUP_SIZE + 1) * (ENC_GROUP_SIZE + 1));
+            assert_eq!(decode_bytes(&encoded), Some((data.clone(), encoded.len())));
+            // Trailing bytes are not consumed
+            let mut with_sub = encoded.clone();
+            with_sub.extend_from_slice(b"_sub");
+            assert_eq!(decode_bytes(&with_sub), Some((data, encoded.len())));
+        }
+    }
+
+    #[test]
+    fn test_bytes_malformed() {
+        let encoded = encode_bytes(b"12345678");
+        assert_eq!(decode_bytes(&encoded[..encoded.len() - 1]), None);
+        assert_eq!(decode_bytes(&encoded[..ENC_GROUP_SIZE + 1]), None);
+        let mut bad_marker = encode_bytes(b"abc");
+        *bad_marker.last_mut().unwrap() = 0;
+        assert_eq!(decode_bytes(&bad_marker), None);
+        assert_eq!(decode_bytes(&[]), None);
+    }
+
+    #[test]
+    fn test_bytes_order() {
+        let mut keys: Vec<Vec<u8>> = vec![
+            vec![],
+            vec![0],
+            b"a".to_vec(),
+            b"a\x00".to_vec(),
+            b"a\x00\x00\x00\x00\x00\x00\x00".to_vec(),
+            b"a\x00\x00\x00\x00\x00\x00\x00\x00".to_vec(),
+            b"ab".to_vec(),
+            b"abcdefgh".to_vec(),
+            b"abcdefghi".to_vec(),
+            b"b".to_vec(),
+            vec![0xFF; 9],
+        ];
+        keys.sort();
+        let encoded: Vec<Vec<u8>> = keys.iter().map(|k| encode_bytes(k)).collect();
+        for i in 1..encoded.len() {
+            assert!(encoded[i - 1] < encoded[i]);
+            assert!(!encoded[i].starts_with(&encoded[i - 1]));
+        }
+    }
+
+    #[test]
+    fn test_decode_data_keys() {
+        let enc = encoder();
+        // Key containing separator and marker bytes
+        let key = b"user_1\xFF\x00".to_vec();
+        assert_eq!(decode_data(enc.encode_string(&key)), DecodedKey::String(key.clone()));
+        assert_eq!(
+            decode_data(enc.encode_string_chunk(&key, 7, 9)),
+            DecodedKey::StringChunk(key.clone(), 7, 9),
+        );
+        assert_eq!(
+            decode_data(enc.encode_hash(&key, b"field_1")),
+            DecodedKey::Hash(key.clone(), b"field_1".to_vec()),
+        );
+        assert_eq!(decode_data(enc.encode_hash_meta_key(&key)), DecodedKey::HashMeta(key.clone()));
+        assert_eq!(decode_data(enc.encode_list_meta_key(&key)), DecodedKey::ListMeta(key.clone()));
+        assert_eq!(
+            decode_data(enc.encode_list_elem_key(&key, -5)),
+            DecodedKey::ListElem(key.clone(), -5),
+        );
+        assert_eq!(
+            decode_data(enc.encode_set(&key, b"")),
+            DecodedKey::Set(key.clone(), Vec::new()),
+        );
+        assert_eq!(
+            decode_data(enc.encode_counter_shard(&key, 1023)),
+            DecodedKey::CounterShard(key.clone(), 1023),
+        );
+        assert_eq!(decode_data(enc.encode_type_key(&key)), DecodedKey::Type(key.clone()));
+        // Empty user key
+        assert_eq!(decode_data(enc.encode_string(b"")), DecodedKey::String(Vec::new()));
+        assert_eq!(decode_data(enc.encode_string(b"12345678")), DecodedKey::String(b"12345678".to_vec()));
+    }
+
+    #[test]
+    fn test_decode_meta_keys() {
+        let enc = encoder();
+        let info = decode(enc.encode_meta_key(META_FORMAT_VERSION));
+        assert_eq!(info.db, None);
+        assert_eq!(info.key, DecodedKey::Meta(META_FORMAT_VERSION.as_bytes().to_vec()));
+        let info = decode(enc.encode_migrate_cursor_key(DataType::Hash));
+        assert_eq!(info.key, DecodedKey::Meta(b"migrate_H".to_vec()));
+    }
+
+    #[test]
+    fn test_decode_legacy_keys() {
+        let prefix = |tp: u8| -> Vec<u8> {
+            let mut ret = DEFAULT_KEY_PREFIX.as_bytes().to_vec();
+            ret.extend_from_slice(&INSTANCE_ID.to_be_bytes());
+            ret.push(b'_');
+            ret.push(tp);
+            ret.push(b'_');
+            ret
+        };
+        let cases: Vec<(Vec<u8>, DecodedKey)> = vec![
+            ([prefix(b'R'), b"k_1".to_vec()].concat(), DecodedKey::String(b"k_1".to_vec())),
+            (
+                [prefix(b'H'), b"D_k_f1".to_vec()].concat(),
+                DecodedKey::Hash(b"k".to_vec(), b"f1".to_vec()),
+            ),
+            ([prefix(b'L'), b"M_k".to_vec()].concat(), DecodedKey::ListMeta(b"k".to_vec())),
+            (
+                [prefix(b'L'), b"D_k_".to_vec(), 3i64.to_be_bytes().to_vec()].concat(),
+                DecodedKey::ListElem(b"k".to_vec(), 3),
+            ),
+            (
+                [prefix(b'S'), b"D_k_m".to_vec()].concat(),
+                DecodedKey::Set(b"k".to_vec(), b"m".to_vec()),
+            ),
+        ];
+        for (rkey, expected) in cases {
+            let info = KeyDecoder::new().decode_key(&rkey).unwrap();
+            assert_eq!(info.instance_id, INSTANCE_ID);
+            assert_eq!(info.version, 0);
+            assert_eq!(info.db, None);
+            assert_eq!(info.key, expected);
+        }
+    }
+
+    #[test]
+    fn test_decode_ambiguous_legacy_keys() {
+        let decoder = KeyDecoder::new();
+        for (tp, data) in [(b'H', &b"D_k_f_1"[..]), (b'S', &b"D_k_1_m"[..])] {
+            let mut rkey = DEFAULT_KEY_PREFIX.as_bytes().to_vec();
+            rkey.extend_from_slice(&INSTANCE_ID.to_be_bytes());
+            rkey.extend_from_slice(&[b'_', tp, b'_']);
+            rkey.extend_from_slice(data);
+            assert!(decoder.decode_legacy_key(rkey.into()).is_none());
+        }
+    }
+
+    #[test]
+    fn test_decode_invalid_keys() {
+        let decoder = KeyDecoder::new();
+        assert!(decoder.decode_key(b"").is_none());
+        assert!(decoder.decode_key(b"other_key").is_none());
+        let mut rkey: Vec<u8> = encoder().encode_string(b"key").into();
+        rkey.truncate(rkey.len() - 1);
+        assert!(decoder.decode_key(&rkey).is_none());
+        let mut rkey: Vec<u8> = encoder().encode_counter_shard(b"key", 1).into();
+        rkey.pop();
+        assert!(decoder.decode_key(&rkey).is_none());
     }
-}
\ No newline at end of file
+}
@@ -1,113 +1,248 @@
 use tikv_client::Key;
-use crate::commands::get_instance_id;
-use super::DataType;
+use crate::{
+    commands::{get_instance_id, get_key_prefix, DEFAULT_KEY_PREFIX},
+    utils::get_current_db,
+};
+use super::{DataType, ENC_GROUP_SIZE, ENC_MARKER, ENC_PAD, FORMAT_VERSION, META_MIGRATE_CURSOR};
+
+// Encode user key as memcomparable bytes (same as TiKV's bytes codec). Data is
+// split into 8 bytes groups, the last group is padded with 0 and every group is
+// followed by a marker byte `0xFF - PAD_SIZE`. So the order of keys is kept and
+// an encoded key never becomes the prefix of another encoded key.
+pub fn encode_bytes(data: &[u8]) -> Vec<u8> {
+    let mut ret = Vec::with_capacity((data.len() / ENC_GROUP_SIZE + 1) * (ENC_GROUP_SIZE + 1));
+    let mut idx = 0;
+    loop {
+        let remain = data.len() - idx;
+        if remain >= ENC_GROUP_SIZE {
+            ret.extend_from_slice(&data[idx..idx + ENC_GROUP_SIZE]);
+            ret.push(ENC_MARKER);
+            idx += ENC_GROUP_SIZE;
+        } else {
+            let pad = ENC_GROUP_SIZE - remain;
+            ret.extend_from_slice(&data[idx..]);
+            ret.extend(std::iter::repeat(ENC_PAD).take(pad));
+            ret.push(ENC_MARKER - pad as u8);
+            return ret;
+        }
+    }
+}
 
 pub struct KeyEncoder {
-    pub instance_id: String,
+    pub key_prefix: Vec<u8>,
+    pub instance_id: [u8; 8],
+    pub db: u32,
 }
 
 impl KeyEncoder {
     pub fn new() -> Self {
-        let inst_id_bytes = get_instance_id().to_be_bytes().to_vec();
-        let inst_id = String::from_utf8(inst_id_bytes).unwrap();
-        KeyEncoder { instance_id: inst_id}
-    }
-
-    fn get_prefix(&self, tp: DataType) -> String {
-        let dt_prefix = match tp {
-            DataType::String => "R",
-            DataType::Hash => "H",
-            DataType::List => "L",
-            DataType::Set => "S",
-        };
-        format!(
-            "x$R_{}_{}",
-            self.instance_id,
-            dt_prefix
-        )
-    }
-
-    pub fn encode_string(&self, key: &str) -> Key {
+        KeyEncoder::with_db(get_current_db())
+    }
+
+    pub fn with_db(db: u32) -> Self {
+        KeyEncoder {
+            key_prefix: get_key_prefix(),
+            instance_id: get_instance_id().to_be_bytes(),
+            db,
+        }
+    }
+
+    // `[KEY_PREFIX][INSTANCE_ID]_`
+    fn get_instance_prefix(&self) -> Vec<u8> {
+        [self.key_prefix.as_slice(), self.instance_id.as_slice(), b"_".as_slice()].concat()
+    }
+
+    pub fn get_type_flag(&self, tp: DataType) -> u8 {
+        match tp {
+            DataType::String => b'R',
+            DataType::Hash => b'H',
+            DataType::List => b'L',
+            DataType::Set => b'S',
+            DataType::Counter => b'C',
+        }
+    }
+
+    // `[KEY_PREFIX][INSTANCE_ID]_[VERSION][DB]`
+    fn get_db_prefix(&self) -> Vec<u8> {
+        let mut ret = self.get_instance_prefix();
+        ret.push(FORMAT_VERSION);
+        ret.extend_from_slice(&self.db.to_be_bytes());
+        ret
+    }
+
+    fn get_prefix(&self, tp: DataType) -> Vec<u8> {
+        let mut ret = self.get_db_prefix();
+        ret.push(self.get_type_flag(tp));
+        ret
+    }
+
+    // Prefix of format version 0: `x$R_[INSTANCE_ID]_[DATATYPE]`, key prefix
+    // is not configurable in legacy format.
+    fn get_legacy_prefix(&self, tp: DataType) -> Vec<u8> {
+        let mut ret = DEFAULT_KEY_PREFIX.as_bytes().to_vec();
+        ret.extend_from_slice(&self.instance_id);
+        ret.push(b'_');
+        ret.push(self.get_type_flag(tp));
+        ret
+    }
+
+    fn concat(&self, parts: &[&[u8]]) -> Key {
+        parts.concat().into()
+    }
+
+    pub fn encode_string(&self, key: &[u8]) -> Key {
         let prefix = self.get_prefix(DataType::String);
-        let ret = format!("{}_{}", prefix, key);
-        ret.into()
+        self.concat(&[&prefix, b"_", &encode_bytes(key)])
     }
 
-    pub fn encode_strings(&self, keys: Vec<String>) -> Vec<Key> {
+    pub fn encode_strings(&self, keys: Vec<Vec<u8>>) -> Vec<Key> {
         let prefix = self.get_prefix(DataType::String);
         keys.into_iter()
-            .map(|val| format!("{}_{}", prefix, val).into())
+            .map(|val| self.concat(&[&prefix, b"_", &encode_bytes(&val)]))
             .collect()
     }
 
     pub fn encode_string_end(&self) -> Key {
         let prefix = self.get_prefix(DataType::String);
-        let ret = format!("{}`", prefix);
-        ret.into()
+        self.concat(&[&prefix, b"`"])
     }
 
-    pub fn encode_hash(&self, key: &str, field: &str) -> Key {
+    // Chunk of chunked string value:
+    // `[PREFIX]RC_[ENCODED_KEY]_[GEN(8Byte)][INDEX(8Byte)]`, it is out of the
+    // range of string keys.
+    pub fn encode_string_chunk(&self, key: &[u8], gen: u64, idx: u64) -> Key {
+        let prefix = self.get_prefix(DataType::String);
+        self.concat(&[&prefix, b"C_", &encode_bytes(key), b"_", &gen.to_be_bytes(), &idx.to_be_bytes()])
+    }
+
+    pub fn encode_string_chunk_start(&self, key: &[u8]) -> Key {
+        let prefix = self.get_prefix(DataType::String);
+        self.concat(&[&prefix, b"C_", &encode_bytes(key), b"_"])
+    }
+
+    pub fn encode_string_chunk_end(&self, key: &[u8]) -> Key {
+        let prefix = self.get_prefix(DataType::String);
+        self.concat(&[&prefix, b"C_", &encode_bytes(key), b"`"])
+    }
+
+    pub fn encode_hash(&self, key: &[u8], field: &[u8]) -> Key {
         let prefix = self.get_prefix(DataType::Hash);
-        let ret = format!("{}_D_{}_{}", prefix, key, field);
-        ret.into()
+        self.concat(&[&prefix, b"_D_", &encode_bytes(key), b"_", field])
     }
 
-    pub fn encode_hash_start(&self, key: &str) -> Key {
+    // Hash metadata (field count): `[PREFIX]H_M_[ENCODED_KEY]`
+    pub fn encode_hash_meta_key(&self, key: &[u8]) -> Key {
         let prefix = self.get_prefix(DataType::Hash);
-        let ret = format!("{}_D_{}_", prefix, key);
-        ret.into()
+        self.concat(&[&prefix, b"_M_", &encode_bytes(key)])
     }
 
-    pub fn encode_hash_end(&self, key: &str) -> Key {
+    pub fn encode_hash_start(&self, key: &[u8]) -> Key {
         let prefix = self.get_prefix(DataType::Hash);
-        let ret = format!("{}_D_{}`", prefix, key);
-        ret.into()
+        self.concat(&[&prefix, b"_D_", &encode_bytes(key), b"_"])
+    }
+
+    pub fn encode_hash_end(&self, key: &[u8]) -> Key {
+        let prefix = self.get_prefix(DataType::Hash);
+        self.concat(&[&prefix, b"_D_", &encode_bytes(key), b"`"])
     }
 
-    pub fn encode_list_meta_key(&self, key: &str) -> Key {
+    pub fn encode_list_meta_key(&self, key: &[u8]) -> Key {
         let prefix = self.get_prefix(DataType::List);
-        let ret = format!("{}_M_{}", prefix, key);
-        ret.into()
+        self.concat(&[&prefix, b"_M_", &encode_bytes(key)])
     }
 
-    pub fn encode_list_elem_key(&self, key: &str, idx: i64) -> Key {
+    pub fn encode_list_elem_key(&self, key: &[u8], idx: i64) -> Key {
         let prefix = self.get_prefix(DataType::List);
-        let mut res = format!("{}_D_{}_", prefix, key).into_bytes();
-        res.append(&mut idx.to_be_bytes().to_vec());
-        res.into()
+        self.concat(&[&prefix, b"_D_", &encode_bytes(key), b"_", &idx.to_be_bytes()])
     }
 
     pub fn encode_list_meta(&self, l: i64, r:i64) -> Vec<u8> {
         [l.to_be_bytes(), r.to_be_bytes()].concat().to_vec()
     }
 
-    pub fn encode_list_elem_start(&self, key: &str) -> Key {
+    pub fn encode_list_elem_start(&self, key: &[u8]) -> Key {
         let prefix = self.get_prefix(DataType::List);
-        let ret = format!("{}_D_{}_", prefix, key).into_bytes();
-        ret.into()
+        self.concat(&[&prefix, b"_D_", &encode_bytes(key), b"_"])
     }
 
-    pub fn encode_list_elem_end(&self, key: &str) -> Key {
+    pub fn encode_list_elem_end(&self, key: &[u8]) -> Key {
         let prefix = self.get_prefix(DataType::List);
-        let ret = format!("{}_D_{}`", prefix, key).into_bytes();
-        ret.into()
+        self.concat(&[&prefix, b"_D_", &encode_bytes(key), b"`"])
     }
 
-    pub fn encode_set(&self, key: &str, member: &str) -> Key {
+    pub fn encode_set(&self, key: &[u8], member: &[u8]) -> Key {
         let prefix = self.get_prefix(DataType::Set);
-        let ret = format!("{}_D_{}_{}", prefix, key, member);
-        ret.into()
+        self.concat(&[&prefix, b"_D_", &encode_bytes(key), b"_", member])
     }
 
-    pub fn encode_set_start(&self, key: &str) -> Key {
+    pub fn encode_set_start(&self, key: &[u8]) -> Key {
         let prefix = self.get_prefix(DataType::Set);
-        let ret = format!("{}_D_{}_", prefix, key);
-        ret.into()
+        self.concat(&[&prefix, b"_D_", &encode_bytes(key), b"_"])
     }
 
-    pub fn encode_set_end(&self, key: &str) -> Key {
+    pub fn encode_set_end(&self, key: &[u8]) -> Key {
         let prefix = self.get_prefix(DataType::Set);
-        let ret = format!("{}_D_{}`", prefix, key);
+        self.concat(&[&prefix, b"_D_", &encode_bytes(key), b"`"])
+    }
+
+    // Shard of sharded counter: `[PREFIX]C_D_[ENCODED_KEY]_[SHARD(4Byte)]`
+    pub fn encode_counter_shard(&self, key: &[u8], shard: u32) -> Key {
+        let prefix = self.get_prefix(DataType::Counter);
+        self.concat(&[&prefix, b"_D_", &encode_bytes(key), b"_", &shard.to_be_bytes()])
+    }
+
+    pub fn encode_counter_start(&self, key: &[u8]) -> Key {
+        let prefix = self.get_prefix(DataType::Counter);
+        self.concat(&[&prefix, b"_D_", &encode_bytes(key), b"_"])
+    }
+
+    pub fn encode_counter_end(&self, key: &[u8]) -> Key {
+        let prefix = self.get_prefix(DataType::Counter);
+        self.concat(&[&prefix, b"_D_", &encode_bytes(key), b"`"])
+    }
+
+    pub fn encode_meta_key(&self, name: &str) -> Key {
+        let prefix = self.get_instance_prefix();
+        self.concat(&[&prefix, b"M_", name.as_bytes()])
+    }
+
+    // Migration cursor of one data type: `[KEY_PREFIX][INSTANCE_ID]_M_migrate_[DATATYPE]`
+    pub fn encode_migrate_cursor_key(&self, tp: DataType) -> Key {
+        let prefix = self.get_instance_prefix();
+        let name = META_MIGRATE_CURSOR.as_bytes();
+        self.concat(&[&prefix, b"M_", name, b"_", &[self.get_type_flag(tp)]])
+    }
+
+    pub fn encode_legacy_start(&self, tp: DataType) -> Key {
+        let prefix = self.get_legacy_prefix(tp);
+        self.concat(&[&prefix, b"_"])
+    }
+
+    pub fn encode_legacy_end(&self, tp: DataType) -> Key {
+        let prefix = self.get_legacy_prefix(tp);
+        self.concat(&[&prefix, b"`"])
+    }
+
+    // Type registry record: `[KEY_PREFIX][INSTANCE_ID]_[VERSION][DB]T_[ENCODED_KEY]`
+    pub fn encode_type_key(&self, key: &[u8]) -> Key {
+        let mut prefix = self.get_db_prefix();
+        prefix.push(b'T');
+        self.concat(&[&prefix, b"_", &encode_bytes(key)])
+    }
+
+    pub fn encode_db_start(&self) -> Key {
+        self.get_db_prefix().into()
+    }
+
+    pub fn encode_db_end(&self) -> Key {
+        let mut ret = self.get_db_prefix();
+        // DB prefix always contains byte less than 0xFF
+        while let Some(b) = ret.pop() {
+            if b < 0xFF {
+                ret.push(b + 1);
+                break;
+            }
+        }
         ret.into()
     }
-}
\ No newline at end of file
+}
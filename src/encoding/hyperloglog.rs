@@ -0,0 +1,381 @@
+// HyperLogLog in Redis's format, so values can be exchanged with Redis:
+//
+// [MAGIC "HYLL"(4Byte)][ENCODING(1Byte)][UNUSED(3Byte)][CARDINALITY(8Byte)][REGISTERS]
+//
+// Dense registers are 16384 6-bit registers, the least significant bits
+// first. `CARDINALITY` is the cached cardinality in little endian, the most
+// significant bit of the last byte is set if the cache is invalid. Sparse
+// values written by Redis can be read, but values are always written dense.
+pub const HLL_MAGIC: &[u8; 4] = b"HYLL";
+pub const HLL_HEADER_LEN: usize = 16;
+pub const HLL_P: usize = 14;
+pub const HLL_Q: usize = 64 - HLL_P;
+pub const HLL_REGISTERS: usize = 1 << HLL_P;
+pub const HLL_BITS: usize = 6;
+pub const HLL_REGISTER_MAX: u8 = (1 << HLL_BITS) - 1;
+pub const HLL_DENSE_SIZE: usize = HLL_HEADER_LEN + (HLL_REGISTERS * HLL_BITS + 7) / 8;
+
+const HLL_DENSE: u8 = 0;
+const HLL_SPARSE: u8 = 1;
+const HLL_ALPHA_INF: f64 = 0.721347520444481703680;
+const HLL_HASH_SEED: u64 = 0xadc83b19;
+
+// MurmurHash64A, same as Redis's HyperLogLog hash function
+fn murmur_hash64a(key: &[u8], seed: u64) -> u64 {
+    const M: u64 = 0xc6a4a7935bd1e995;
+    const R: u32 = 47;
+    let mut h = seed ^ (key.len() as u64).wrapping_mul(M);
+    let mut chunks = key.chunks_exact(8);
+    for chunk in &mut chunks {
+        let mut k = u64::from_le_bytes(chunk.try_into().unwrap());
+        k = k.wrapping_mul(M);
+        k ^= k >> R;
+        k = k.wrapping_mul(M);
+        h ^= k;
+        h = h.wrapping_mul(M);
+    }
+    let tail = chunks.remainder();
+    if !tail.is_empty() {
+        for (i, b) in tail.iter().enumerate() {
+            h ^= (*b as u64) << (8 * i);
+        }
+        h = h.wrapping_mul(M);
+    }
+    h ^= h >> R;
+    h = h.wrapping_mul(M);
+    h ^= h >> R;
+    h
+}
+
+// Register index of element and the length of its 000..1 pattern
+fn hll_pattern_len(element: &[u8]) -> (usize, u8) {
+    let hash = murmur_hash64a(element, HLL_HASH_SEED);
+    let index = (hash as usize) & (HLL_REGISTERS - 1);
+    let bits = (hash >> HLL_P) | (1 << HLL_Q);
+    (index, bits.trailing_zeros() as u8 + 1)
+}
+
+fn hll_sigma(mut x: f64) -> f64 {
+    if x == 1.0 {
+        return f64::INFINITY;
+    }
+    let mut y = 1.0;
+    let mut z = x;
+    loop {
+        x *= x;
+        let z_prime = z;
+        z += x * y;
+        y += y;
+        if z_prime == z {
+            return z;
+        }
+    }
+}
+
+fn hll_tau(mut x: f64) -> f64 {
+    if x == 0.0 || x == 1.0 {
+        return 0.0;
+    }
+    let mut y = 1.0;
+    let mut z = 1.0 - x;
+    loop {
+        x = x.sqrt();
+        let z_prime = z;
+        y *= 0.5;
+        z -= (1.0 - x).powi(2) * y;
+        if z_prime == z {
+            return z / 3.0;
+        }
+    }
+}
+
+pub struct HyperLogLog {
+    registers: Vec<u8>,
+    cached: Option<u64>,
+}
+
+impl HyperLogLog {
+    pub fn new() -> Self {
+        HyperLogLog {
+            registers: vec![0; HLL_REGISTERS],
+            cached: Some(0),
+        }
+    }
+
+    // Decode dense or sparse HyperLogLog, returns None if it is invalid
+    pub fn decode(data: &[u8]) -> Option<Self> {
+        if data.len() < HLL_HEADER_LEN || &data[..4] != HLL_MAGIC {
+            return None;
+        }
+        let card = &data[8..HLL_HEADER_LEN];
+        let cached = if card[7] & 0x80 == 0 {
+            Some(u64::from_le_bytes(card.try_into().unwrap()))
+        } else {
+            None
+        };
+        let registers = match data[4] {
+            HLL_DENSE if data.len() == HLL_DENSE_SIZE => {
+                let dense = &data[HLL_HEADER_LEN..];
+                (0..HLL_REGISTERS).map(|idx| get_dense_register(dense, idx)).collect()
+            }
+            HLL_SPARSE => decode_sparse(&data[HLL_HEADER_LEN..])?,
+            _ => return None,
+        };
+        Some(HyperLogLog { registers, cached })
+    }
+
+    // Encode as dense HyperLogLog
+    pub fn encode(&self) -> Vec<u8> {
+        let mut ret = vec![0u8; HLL_DENSE_SIZE];
+        ret[..4].copy_from_slice(HLL_MAGIC);
+        ret[4] = HLL_DENSE;
+        match self.cached {
+            Some(card) => ret[8..HLL_HEADER_LEN].copy_from_slice(&card.to_le_bytes()),
+            None => ret[15] |= 0x80,
+        }
+        let dense = &mut ret[HLL_HEADER_LEN..];
+        for (idx, val) in self.registers.iter().enumerate() {
+            set_dense_register(dense, idx, *val);
+        }
+        ret
+    }
+
+    // Add element, returns true if any register is changed
+    pub fn add(&mut self, element: &[u8]) -> bool {
+        let (index, count) = hll_pattern_len(element);
+        if self.registers[index] < count {
+            self.registers[index] = count;
+            self.cached = None;
+            true
+        } else {
+            false
+        }
+    }
+
+    // Merge registers of other HyperLogLog by taking the max value
+    pub fn merge(&mut self, other: &HyperLogLog) {
+        for (reg, val) in self.registers.iter_mut().zip(other.registers.iter()) {
+            if *val > *reg {
+                *reg = *val;
+                self.cached = None;
+            }
+        }
+    }
+
+    pub fn cached_count(&self) -> Option<u64> {
+        self.cached
+    }
+
+    // Estimate cardinality with the same algorithm as Redis, result is cached
+    pub fn count(&mut self) -> u64 {
+        if let Some(card) = self.cached {
+            return card;
+        }
+        let m = HLL_REGISTERS as f64;
+        let mut histo = [0u32; 64];
+        for val in self.registers.iter() {
+            histo[*val as usize] += 1;
+        }
+        let mut z = m * hll_tau((m - histo[HLL_Q + 1] as f64) / m);
+        for j in (1..=HLL_Q).rev() {
+            z += histo[j] as f64;
+            z *= 0.5;
+        }
+        z += m * hll_sigma(histo[0] as f64 / m);
+        let card = (HLL_ALPHA_INF * m * m / z).round() as u64;
+        self.cached = Some(card);
+        card
+    }
+}
+
+fn get_dense_register(dense: &[u8], idx: usize) -> u8 {
+    let byte = idx * HLL_BITS / 8;
+    let fb = idx * HLL_BITS & 7;
+    let b0 = dense[byte] as u16;
+    let b1 = dense.get(byte + 1).copied().unwrap_or(0) as u16;
+    (((b0 >> fb) | (b1 << (8 - fb))) as u8) & HLL_REGISTER_MAX
+}
+
+fn set_dense_register(dense: &mut [u8], idx: usize, val: u8) {
+    let byte = idx * HLL_BITS / 8;
+    let fb = idx * HLL_BITS & 7;
+    let val = val as u16;
+    let max = HLL_REGISTER_MAX as u16;
+    dense[byte] &= !((max << fb) as u8);
+    dense[byte] |= (val << fb) as u8;
+    if byte + 1 < dense.len() {
+        dense[byte + 1] &= !((max >> (8 - fb)) as u8);
+        dense[byte + 1] |= (val >> (8 - fb)) as u8;
+    }
+}
+
+// Sparse opcodes: ZERO `00xxxxxx`, XZERO `01xxxxxx yyyyyyyy` and VAL `1vvvvvxx`
+fn decode_sparse(data: &[u8]) -> Option<Vec<u8>> {
+    let mut registers = vec![0u8; HLL_REGISTERS];
+    let mut idx = 0;
+    let mut pos = 0;
+    while pos < data.len() {
+        let op = data[pos];
+        if op & 0xC0 == 0x00 {
+            idx += (op & 0x3F) as usize + 1;
+            pos += 1;
+        } else if op & 0xC0 == 0x40 {
+            let next = *data.get(pos + 1)? as usize;
+            idx += (((op & 0x3F) as usize) << 8 | next) + 1;
+            pos += 2;
+        } else {
+            let val = ((op >> 2) & 0x1F) + 1;
+            let len = (op & 0x03) as usize + 1;
+            if idx + len > HLL_REGISTERS {
+                return None;
+            }
+            registers[idx..idx + len].fill(val);
+            idx += len;
+            pos += 1;
+        }
+        if idx > HLL_REGISTERS {
+            return None;
+        }
+    }
+    if idx != HLL_REGISTERS {
+        return None;
+    }
+    Some(registers)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hll_of(elements: &[&str]) -> HyperLogLog {
+        let mut hll = HyperLogLog::new();
+        for e in elements {
+            hll.add(e.as_bytes());
+        }
+        hll
+    }
+
+    // Sparse value with XZERO runs around a single register
+    fn sparse_with(index: usize, val: u8) -> Vec<u8> {
+        let mut ret = HLL_MAGIC.to_vec();
+        ret.extend_from_slice(&[HLL_SPARSE, 0, 0, 0]);
+        ret.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 0x80]);
+        let xzero = |ret: &mut Vec<u8>, len: usize| {
+            if len > 0 {
+                ret.push(0x40 | ((len - 1) >> 8) as u8);
+                ret.push(((len - 1) & 0xFF) as u8);
+            }
+        };
+        xzero(&mut ret, index);
+        ret.push(0x80 | ((val - 1) << 2));
+        xzero(&mut ret, HLL_REGISTERS - index - 1);
+        ret
+    }
+
+    #[test]
+    fn test_count_redis_examples() {
+        // Examples from Redis documentation and test suite
+        assert_eq!(hll_of(&["a", "b", "c", "d", "e", "f", "g"]).count(), 7);
+        let mut hll = hll_of(&["1", "2", "3", "4", "5"]);
+        assert_eq!(hll.count(), 5);
+        for e in ["6", "7", "8", "8", "9", "10"] {
+            hll.add(e.as_bytes());
+        }
+        assert_eq!(hll.count(), 10);
+        let mut merged = hll_of(&["foo", "bar", "zap", "a"]);
+        merged.merge(&hll_of(&["a", "b", "c", "foo"]));
+        assert_eq!(merged.count(), 6);
+        assert_eq!(HyperLogLog::new().count(), 0);
+    }
+
+    #[test]
+    fn test_add_and_cache() {
+        let mut hll = HyperLogLog::new();
+        assert_eq!(hll.cached_count(), Some(0));
+        assert!(hll.add(b"foo"));
+        assert_eq!(hll.cached_count(), None);
+        assert!(!hll.add(b"foo"));
+        assert_eq!(hll.count(), 1);
+        assert_eq!(hll.cached_count(), Some(1));
+        let mut other = hll_of(&["foo"]);
+        hll.merge(&other);
+        assert_eq!(hll.cached_count(), Some(1));
+        assert_eq!(other.count(), 1);
+    }
+
+    #[test]
+    fn test_count_accuracy() {
+        // Standard error is 0.81%, Redis tests accept 5%
+        for total in [1000u64, 20000, 100000] {
+            let mut hll = HyperLogLog::new();
+            for i in 0..total {
+                hll.add(format!("element:{}", i).as_bytes());
+            }
+            let card = hll.count();
+            assert!(card.abs_diff(total) * 100 <= total * 5, "{} estimated as {}", total, card);
+        }
+    }
+
+    #[test]
+    fn test_dense_round_trip() {
+        let mut hll = HyperLogLog::new();
+        for i in 0..5000 {
+            hll.add(format!("element:{}", i).as_bytes());
+        }
+        // Register values cross byte boundaries at both ends
+        hll.registers[0] = HLL_REGISTER_MAX;
+        hll.registers[HLL_REGISTERS - 1] = HLL_REGISTER_MAX;
+        let encoded = hll.encode();
+        assert_eq!(encoded.len(), HLL_DENSE_SIZE);
+        assert_eq!(&encoded[..5], b"HYLL\x00");
+        assert_eq!(encoded[15] & 0x80, 0x80);
+        let decoded = HyperLogLog::decode(&encoded).unwrap();
+        assert_eq!(decoded.registers, hll.registers);
+        assert_eq!(decoded.cached_count(), None);
+
+        let card = hll.count();
+        let mut decoded = HyperLogLog::decode(&hll.encode()).unwrap();
+        assert_eq!(decoded.cached_count(), Some(card));
+        assert_eq!(decoded.count(), card);
+    }
+
+    #[test]
+    fn test_decode_sparse() {
+        // Empty HyperLogLog created by Redis `PFADD key`
+        let empty = b"HYLL\x01\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x7f\xff";
+        let mut hll = HyperLogLog::decode(empty).unwrap();
+        assert_eq!(hll.cached_count(), Some(0));
+        assert_eq!(hll.count(), 0);
+        assert!(hll.registers.iter().all(|v| *v == 0));
+
+        let (index, count) = hll_pattern_len(b"foo");
+        let mut sparse = HyperLogLog::decode(&sparse_with(index, count)).unwrap();
+        assert_eq!(sparse.registers, hll_of(&["foo"]).registers);
+        assert_eq!(sparse.count(), 1);
+        for index in [0, 1, HLL_REGISTERS - 1] {
+            let hll = HyperLogLog::decode(&sparse_with(index, 32)).unwrap();
+            assert_eq!(hll.registers[index], 32);
+            assert_eq!(hll.registers.iter().filter(|v| **v != 0).count(), 1);
+        }
+    }
+
+    #[test]
+    fn test_decode_invalid() {
+        let dense = HyperLogLog::new().encode();
+        assert!(HyperLogLog::decode(&dense[..HLL_DENSE_SIZE - 1]).is_none());
+        assert!(HyperLogLog::decode(&dense[..HLL_HEADER_LEN - 1]).is_none());
+        let mut bad_magic = dense.clone();
+        bad_magic[0] = b'X';
+        assert!(HyperLogLog::decode(&bad_magic).is_none());
+        let mut bad_encoding = dense;
+        bad_encoding[4] = 2;
+        assert!(HyperLogLog::decode(&bad_encoding).is_none());
+        // Sparse registers must sum up to exactly `HLL_REGISTERS`
+        let mut short = sparse_with(0, 1);
+        short.pop();
+        assert!(HyperLogLog::decode(&short).is_none());
+        let mut long = sparse_with(0, 1);
+        long.push(0x00);
+        assert!(HyperLogLog::decode(&long).is_none());
+    }
+}
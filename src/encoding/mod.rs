@@ -1,16 +1,41 @@
 pub mod encode;
 pub mod decode;
+pub mod value;
+pub mod hyperloglog;
 
 pub const EMPTY_VALUE: Vec<u8> = vec![];
 
+// Memcomparable encoding for user keys, 8 bytes a group and 1 marker byte.
+pub const ENC_GROUP_SIZE: usize = 8;
+pub const ENC_MARKER: u8 = 0xFF;
+pub const ENC_PAD: u8 = 0x0;
+
+// Key format version, written into every data key after the instance id.
+// Version 0 is the legacy layout without version byte and key encoding.
+pub const FORMAT_VERSION: u8 = 1;
+
+// Size of chunk keys for chunked string values such as large bitmaps
+pub const CHUNK_SIZE: usize = 64 * 1024;
+
+// Per-instance metadata names
+pub const META_FORMAT_VERSION: &str = "version";
+pub const META_MIGRATE_CURSOR: &str = "migrate";
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum DataType {
     String,
     Hash,
     List,
     Set,
+    Counter,
 }
 
 pub use {
-    encode::KeyEncoder,
-    decode::KeyDecoder,
+    encode::{KeyEncoder, encode_bytes},
+    decode::{KeyDecoder, LegacyKey, DecodedKey, KeyInfo, decode_bytes},
+    value::{
+        ValueEncoder, ValueDecoder, ValueType, DecodedValue, ChunkManifest, Compression,
+        now_millis, ttl_from_expire_at,
+    },
+    hyperloglog::HyperLogLog,
 };
\ No newline at end of file
@@ -0,0 +1,435 @@
+use std::io::{Read, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+use flate2::{read::DeflateDecoder, write::DeflateEncoder};
+use crate::commands::{errors::{AsyncResult, RTError}, get_compression};
+
+// Value envelope format:
+//
+// [MAGIC(3Byte)][VERSION(1Byte)][TYPE(1Byte)][FLAGS(1Byte)][EXPIRE_AT(8Byte)][DATA(nByte)]
+//
+// `EXPIRE_AT` is unix timestamp in milliseconds, only exists when
+// `FLAG_EXPIRE_AT` is set. If `FLAG_COMPRESSED` is set the first byte of
+// `DATA` is the compression algorithm and the rest is compressed data. Values
+// without the magic are headerless values written by old versions, they are
+// treated as raw data.
+pub const VALUE_MAGIC: [u8; 3] = [0xFF, b'R', b'V'];
+pub const VALUE_VERSION: u8 = 1;
+pub const VALUE_HEADER_LEN: usize = 6;
+
+pub const FLAG_COMPRESSED: u8 = 0x01;
+pub const FLAG_EXPIRE_AT: u8 = 0x02;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Compression {
+    None,
+    Lz4,
+    Deflate,
+}
+
+impl Compression {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "none" => Some(Compression::None),
+            "lz4" => Some(Compression::Lz4),
+            "deflate" => Some(Compression::Deflate),
+            _ => None,
+        }
+    }
+
+    fn to_tag(self) -> u8 {
+        match self {
+            Compression::None => 0,
+            Compression::Lz4 => 1,
+            Compression::Deflate => 2,
+        }
+    }
+
+    fn from_tag(tag: u8) -> Option<Self> {
+        match tag {
+            0 => Some(Compression::None),
+            1 => Some(Compression::Lz4),
+            2 => Some(Compression::Deflate),
+            _ => None,
+        }
+    }
+
+    fn compress(self, data: &[u8]) -> Option<Vec<u8>> {
+        match self {
+            Compression::None => None,
+            Compression::Lz4 => Some(lz4_flex::compress_prepend_size(data)),
+            Compression::Deflate => {
+                let mut encoder = DeflateEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(data).ok()?;
+                encoder.finish().ok()
+            }
+        }
+    }
+
+    fn decompress(self, data: &[u8]) -> Option<Vec<u8>> {
+        match self {
+            Compression::None => Some(data.to_vec()),
+            Compression::Lz4 => lz4_flex::decompress_size_prepended(data).ok(),
+            Compression::Deflate => {
+                let mut ret = Vec::new();
+                DeflateDecoder::new(data).read_to_end(&mut ret).ok()?;
+                Some(ret)
+            }
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ValueType {
+    Raw,
+    Integer,
+    // Data is a `ChunkManifest`, value is stored in chunk keys
+    Chunked,
+}
+
+impl ValueType {
+    fn to_tag(self) -> u8 {
+        match self {
+            ValueType::Raw => 0,
+            ValueType::Integer => 1,
+            ValueType::Chunked => 2,
+        }
+    }
+
+    fn from_tag(tag: u8) -> Option<Self> {
+        match tag {
+            0 => Some(ValueType::Raw),
+            1 => Some(ValueType::Integer),
+            2 => Some(ValueType::Chunked),
+            _ => None,
+        }
+    }
+}
+
+static CHUNK_GEN_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+// Manifest of chunked value: `[LEN(8Byte)][GEN(8Byte)]`. Every write of a new
+// chunked value uses a new `GEN` in its chunk keys, so chunks left by failed
+// or overwritten writes are never read as part of current value.
+#[derive(Copy, Clone, Debug)]
+pub struct ChunkManifest {
+    pub len: u64,
+    pub gen: u64,
+}
+
+impl ChunkManifest {
+    pub fn new(len: u64) -> Self {
+        let counter = CHUNK_GEN_COUNTER.fetch_add(1, Ordering::Relaxed);
+        ChunkManifest {
+            len,
+            gen: (now_millis() << 16) | (counter & 0xFFFF),
+        }
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        [self.len.to_be_bytes(), self.gen.to_be_bytes()].concat()
+    }
+
+    pub fn decode(data: &[u8]) -> Option<Self> {
+        if data.len() != 16 {
+            return None;
+        }
+        Some(ChunkManifest {
+            len: u64::from_be_bytes(data[..8].try_into().unwrap()),
+            gen: u64::from_be_bytes(data[8..].try_into().unwrap()),
+        })
+    }
+}
+
+pub struct DecodedValue {
+    pub tp: ValueType,
+    pub flags: u8,
+    pub expire_at: Option<u64>,
+    pub data: Vec<u8>,
+}
+
+impl DecodedValue {
+    pub fn is_expired(&self) -> bool {
+        match self.expire_at {
+            Some(ts) => ts <= now_millis(),
+            None => false,
+        }
+    }
+
+    pub fn manifest(&self) -> Option<ChunkManifest> {
+        match self.tp {
+            ValueType::Chunked => ChunkManifest::decode(&self.data),
+            _ => None,
+        }
+    }
+}
+
+pub fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+// TiKV TTL in seconds for given expire timestamp in milliseconds. TTL is
+// rounded up so TiKV never removes value before it is expired, and value is
+// treated as not exists by `EXPIRE_AT` before TiKV removes it.
+pub fn ttl_from_expire_at(expire_at: u64) -> u64 {
+    let now = now_millis();
+    if expire_at <= now {
+        return 1;
+    }
+    (expire_at - now + 999) / 1000
+}
+
+// Parse value header, returns type, flags, expire time and offset of data
+fn parse_header(value: &[u8]) -> Option<(ValueType, u8, Option<u64>, usize)> {
+    if value.len() < VALUE_HEADER_LEN || value[..3] != VALUE_MAGIC || value[3] != VALUE_VERSION {
+        return None;
+    }
+    let tp = ValueType::from_tag(value[4])?;
+    let flags = value[5];
+    let mut offset = VALUE_HEADER_LEN;
+    let mut expire_at = None;
+    if flags & FLAG_EXPIRE_AT != 0 {
+        let ts = value.get(offset..offset + 8)?;
+        expire_at = Some(u64::from_be_bytes(ts.try_into().unwrap()));
+        offset += 8;
+    }
+    Some((tp, flags, expire_at, offset))
+}
+
+pub struct ValueEncoder {
+    compression: Compression,
+    compress_threshold: usize,
+}
+
+impl ValueEncoder {
+    pub fn new() -> Self {
+        let (compression, compress_threshold) = get_compression();
+        ValueEncoder{
+            compression,
+            compress_threshold,
+        }
+    }
+
+    pub fn encode(&self, tp: ValueType, data: &[u8]) -> Vec<u8> {
+        self.encode_with_flags(tp, 0, None, data)
+    }
+
+    pub fn encode_raw(&self, data: &[u8]) -> Vec<u8> {
+        self.encode(ValueType::Raw, data)
+    }
+
+    // Encode user data, data is compressed if compression is enabled and data
+    // size reaches the threshold. Compressed data will only be kept if it is
+    // smaller than origin data.
+    pub fn encode_compressible(&self, data: &[u8]) -> Vec<u8> {
+        self.encode_compressible_with_expire(data, None)
+    }
+
+    pub fn encode_compressible_with_expire(&self, data: &[u8], expire_at: Option<u64>) -> Vec<u8> {
+        if self.compression == Compression::None || data.len() < self.compress_threshold {
+            return self.encode_with_flags(ValueType::Raw, 0, expire_at, data);
+        }
+        match self.compression.compress(data) {
+            Some(compressed) if compressed.len() + 1 < data.len() => {
+                let mut cdata = Vec::with_capacity(compressed.len() + 1);
+                cdata.push(self.compression.to_tag());
+                cdata.extend_from_slice(&compressed);
+                self.encode_with_flags(ValueType::Raw, FLAG_COMPRESSED, expire_at, &cdata)
+            }
+            _ => self.encode_with_flags(ValueType::Raw, 0, expire_at, data),
+        }
+    }
+
+    // Change expire time of an encoded value, value type and data (may be
+    // compressed) are kept. Headerless value is encoded as raw value.
+    pub fn replace_expire_at(&self, value: &[u8], expire_at: Option<u64>) -> Vec<u8> {
+        match parse_header(value) {
+            Some((tp, flags, _, offset)) => self.encode_with_flags(tp, flags, expire_at, &value[offset..]),
+            None => self.encode_with_flags(ValueType::Raw, 0, expire_at, value),
+        }
+    }
+
+    pub fn encode_with_flags(
+        &self,
+        tp: ValueType,
+        flags: u8,
+        expire_at: Option<u64>,
+        data: &[u8],
+    ) -> Vec<u8> {
+        let mut ret = Vec::with_capacity(VALUE_HEADER_LEN + 8 + data.len());
+        ret.extend_from_slice(&VALUE_MAGIC);
+        ret.push(VALUE_VERSION);
+        ret.push(tp.to_tag());
+        match expire_at {
+            Some(ts) => {
+                ret.push(flags | FLAG_EXPIRE_AT);
+                ret.extend_from_slice(&ts.to_be_bytes());
+            }
+            None => ret.push(flags & !FLAG_EXPIRE_AT),
+        }
+        ret.extend_from_slice(data);
+        ret
+    }
+}
+
+pub struct ValueDecoder {}
+
+impl ValueDecoder {
+    pub fn new() -> Self {
+        ValueDecoder{}
+    }
+
+    // Returns None for headerless value. Compressed data that can not be
+    // decompressed is an error, it must not be returned as user data.
+    fn decode_header(&self, value: &[u8]) -> AsyncResult<Option<DecodedValue>> {
+        let (tp, mut flags, expire_at, offset) = match parse_header(value) {
+            Some(header) => header,
+            None => return Ok(None),
+        };
+        let mut data = value[offset..].to_vec();
+        if flags & FLAG_COMPRESSED != 0 {
+            let algorithm = data
+                .first()
+                .and_then(|tag| Compression::from_tag(*tag))
+                .ok_or(RTError::CorruptValue)?;
+            data = algorithm.decompress(&data[1..]).ok_or(RTError::CorruptValue)?;
+            flags &= !FLAG_COMPRESSED;
+        }
+        Ok(Some(DecodedValue {
+            tp,
+            flags,
+            expire_at,
+            data,
+        }))
+    }
+
+    pub fn decode(&self, value: Vec<u8>) -> AsyncResult<DecodedValue> {
+        match self.decode_header(&value)? {
+            Some(decoded) => Ok(decoded),
+            None => Ok(DecodedValue {
+                tp: ValueType::Raw,
+                flags: 0,
+                expire_at: None,
+                data: value,
+            }),
+        }
+    }
+
+    // Get user data from stored value, returns None if value is expired.
+    pub fn decode_data(&self, value: Vec<u8>) -> AsyncResult<Option<Vec<u8>>> {
+        let decoded = self.decode(value)?;
+        if decoded.is_expired() {
+            return Ok(None);
+        }
+        Ok(Some(decoded.data))
+    }
+
+    pub fn decode_opt(&self, value: Option<Vec<u8>>) -> AsyncResult<Option<Vec<u8>>> {
+        match value {
+            Some(v) => self.decode_data(v),
+            None => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encoder(compression: Compression) -> ValueEncoder {
+        ValueEncoder {
+            compression,
+            compress_threshold: 64,
+        }
+    }
+
+    #[test]
+    fn test_raw_value() {
+        let val = encoder(Compression::None).encode_raw(b"hello");
+        assert_eq!(val.len(), VALUE_HEADER_LEN + 5);
+        let decoded = ValueDecoder::new().decode(val).unwrap();
+        assert_eq!(decoded.tp, ValueType::Raw);
+        assert_eq!(decoded.flags, 0);
+        assert_eq!(decoded.expire_at, None);
+        assert_eq!(decoded.data, b"hello");
+    }
+
+    #[test]
+    fn test_value_with_expire_at() {
+        let vencoder = encoder(Compression::None);
+        let vdecoder = ValueDecoder::new();
+        let expire_at = now_millis() + 60_000;
+        let val = vencoder.encode_with_flags(ValueType::Integer, 0, Some(expire_at), b"42");
+        assert_eq!(val.len(), VALUE_HEADER_LEN + 8 + 2);
+        let decoded = vdecoder.decode(val.clone()).unwrap();
+        assert_eq!(decoded.tp, ValueType::Integer);
+        assert_eq!(decoded.expire_at, Some(expire_at));
+        assert!(!decoded.is_expired());
+        assert_eq!(decoded.data, b"42");
+
+        let persisted = vencoder.replace_expire_at(&val, None);
+        let decoded = vdecoder.decode(persisted).unwrap();
+        assert_eq!(decoded.tp, ValueType::Integer);
+        assert_eq!(decoded.expire_at, None);
+        assert_eq!(decoded.data, b"42");
+
+        let expired = vencoder.encode_with_flags(ValueType::Raw, 0, Some(0), b"gone");
+        assert!(vdecoder.decode(expired.clone()).unwrap().is_expired());
+        assert_eq!(vdecoder.decode_data(expired).unwrap(), None);
+    }
+
+    #[test]
+    fn test_headerless_value() {
+        let decoded = ValueDecoder::new().decode(b"legacy value".to_vec()).unwrap();
+        assert_eq!(decoded.tp, ValueType::Raw);
+        assert_eq!(decoded.expire_at, None);
+        assert_eq!(decoded.data, b"legacy value");
+    }
+
+    #[test]
+    fn test_compressed_value() {
+        let data = b"0123456789abcdef".repeat(64);
+        let expire_at = now_millis() + 60_000;
+        for compression in [Compression::Lz4, Compression::Deflate] {
+            let vencoder = encoder(compression);
+            for expire in [None, Some(expire_at)] {
+                let val = vencoder.encode_compressible_with_expire(&data, expire);
+                assert!(val.len() < data.len());
+                assert_eq!(val[5] & FLAG_COMPRESSED, FLAG_COMPRESSED);
+                let decoded = ValueDecoder::new().decode(val).unwrap();
+                assert_eq!(decoded.flags & FLAG_COMPRESSED, 0);
+                assert_eq!(decoded.expire_at, expire);
+                assert_eq!(decoded.data, data);
+            }
+        }
+    }
+
+    #[test]
+    fn test_small_value_not_compressed() {
+        let val = encoder(Compression::Lz4).encode_compressible(b"short");
+        assert_eq!(val[5] & FLAG_COMPRESSED, 0);
+        assert_eq!(ValueDecoder::new().decode(val).unwrap().data, b"short");
+    }
+
+    #[test]
+    fn test_corrupt_compressed_value() {
+        let vdecoder = ValueDecoder::new();
+        let data = b"0123456789abcdef".repeat(64);
+        for compression in [Compression::Lz4, Compression::Deflate] {
+            let mut val = encoder(compression).encode_compressible(&data);
+            val.truncate(val.len() - 8);
+            assert!(vdecoder.decode(val).is_err());
+        }
+        let vencoder = encoder(Compression::None);
+        // Unsupported algorithm
+        let val = vencoder.encode_with_flags(ValueType::Raw, FLAG_COMPRESSED, None, &[9, 1, 2, 3]);
+        assert!(vdecoder.decode(val.clone()).is_err());
+        assert!(vdecoder.decode_opt(Some(val)).is_err());
+        // Missing algorithm
+        let val = vencoder.encode_with_flags(ValueType::Raw, FLAG_COMPRESSED, None, &[]);
+        assert!(vdecoder.decode(val).is_err());
+    }
+}
@@ -2,6 +2,7 @@ use crate::commands::asyncs::connection::{do_async_txn_connect, do_async_raw_con
 use crate::metrics::prometheus_server;
 use crate::{
     commands::*,
+    encoding::Compression,
     metrics::INSTANCE_ID_GAUGER,
     try_redis_command,
 };
@@ -26,9 +27,20 @@ pub fn tikv_init(ctx: &Context, args: &Vec<RedisString>) -> Status {
     let mut pd_addrs: String = String::from("");
     let mut enable_prometheus_http: bool = false;
     let mut threads: usize = 32;
+    let mut invalid_instance_id: Option<String> = None;
+    let mut key_prefix: Option<Vec<u8>> = None;
+    let mut compression = Compression::None;
+    let mut compress_threshold: usize = DEFAULT_COMPRESS_THRESHOLD;
+    let mut invalid_compress_arg: Option<String> = None;
+    let mut counter_shards: u32 = DEFAULT_COUNTER_SHARDS;
+    let mut invalid_counter_shards: Option<String> = None;
     if args.len() > 0 {
         let mut start_pd_addrs = false;
         let mut start_instance_id = false;
+        let mut start_key_prefix = false;
+        let mut start_compress = false;
+        let mut start_compress_threshold = false;
+        let mut start_counter_shards = false;
         let mut start_threads = false;
         let mut start_replace_system = false;
         let mut start_execute_mode = false;
@@ -62,12 +74,65 @@ pub fn tikv_init(ctx: &Context, args: &Vec<RedisString>) -> Status {
                 let instance_id_str = ss.clone();
                 match instance_id_str.parse::<u64>() {
                     Ok(val) => set_instance_id(val),
-                    Err(_) => set_instance_id(0),
+                    Err(_) => {
+                        invalid_instance_id.replace(instance_id_str);
+                    }
                 };
                 INSTANCE_ID_GAUGER.set(get_instance_id() as i64);
                 start_instance_id = false;
                 return;
             }
+            if ss == "keyprefix" {
+                start_key_prefix = true;
+                return;
+            }
+            if start_key_prefix {
+                key_prefix.replace(s.as_slice().to_vec());
+                start_key_prefix = false;
+                return;
+            }
+            if ss == "compress" {
+                start_compress = true;
+                return;
+            }
+            if start_compress {
+                match Compression::from_name(&ss) {
+                    Some(algorithm) => compression = algorithm,
+                    None => {
+                        invalid_compress_arg.replace(format!("compress `{}`", ss));
+                    }
+                };
+                start_compress = false;
+                return;
+            }
+            if ss == "compressthreshold" {
+                start_compress_threshold = true;
+                return;
+            }
+            if start_compress_threshold {
+                match ss.parse::<usize>() {
+                    Ok(val) => compress_threshold = val,
+                    Err(_) => {
+                        invalid_compress_arg.replace(format!("compressthreshold `{}`", ss));
+                    }
+                };
+                start_compress_threshold = false;
+                return;
+            }
+            if ss == "countershards" {
+                start_counter_shards = true;
+                return;
+            }
+            if start_counter_shards {
+                match ss.parse::<u32>() {
+                    Ok(val) if val > 0 && val <= MAX_COUNTER_SHARDS => counter_shards = val,
+                    _ => {
+                        invalid_counter_shards.replace(ss.clone());
+                    }
+                };
+                start_counter_shards = false;
+                return;
+            }
             if ss == "enablepromhttp" {
                 enable_prometheus_http = true;
                 return;
@@ -106,6 +171,44 @@ pub fn tikv_init(ctx: &Context, args: &Vec<RedisString>) -> Status {
         });
     }
 
+    if let Some(inst_id) = invalid_instance_id {
+        ctx.log_warning(&format!(
+            "Invalid instanceid parameter `{}`, it should be an unsigned 64 bit integer",
+            inst_id
+        ));
+        return Status::Err;
+    }
+
+    if let Some(prefix) = key_prefix {
+        // Keep module data away from TiDB's table (`t`) and meta (`m`) data
+        if prefix.is_empty() || prefix[0] == b't' || prefix[0] == b'm' {
+            ctx.log_warning(&format!(
+                "Invalid keyprefix parameter `{}`, it should not be empty or start with `t` or `m`",
+                String::from_utf8_lossy(&prefix)
+            ));
+            return Status::Err;
+        }
+        set_key_prefix(&prefix);
+    }
+
+    if let Some(arg) = invalid_compress_arg {
+        ctx.log_warning(&format!(
+            "Invalid {} parameter, compress should be none, lz4 or deflate and compressthreshold should be a number",
+            arg
+        ));
+        return Status::Err;
+    }
+    set_compression(compression, compress_threshold);
+
+    if let Some(shards) = invalid_counter_shards {
+        ctx.log_warning(&format!(
+            "Invalid countershards parameter `{}`, it should be a number from 1 to {}",
+            shards, MAX_COUNTER_SHARDS
+        ));
+        return Status::Err;
+    }
+    set_counter_shards(counter_shards);
+
     thread::Builder::new()
         .name("tokio-worker-1".into())
         .spawn(move || {
@@ -217,6 +320,16 @@ pub fn tikv_init(ctx: &Context, args: &Vec<RedisString>) -> Status {
             try_redis_command!(ctx, "mset", tikv_raw_batch_set, "", 0, 0, 0);
             try_redis_command!(ctx, "incr", tikv_raw_incr, "", 0, 0, 0);
             try_redis_command!(ctx, "decr", tikv_raw_decr, "", 0, 0, 0);
+            try_redis_command!(ctx, "getset", tikv_raw_getset, "", 0, 0, 0);
+            try_redis_command!(ctx, "getdel", tikv_raw_getdel, "", 0, 0, 0);
+            try_redis_command!(ctx, "getex", tikv_raw_getex, "", 0, 0, 0);
+            try_redis_command!(ctx, "expire", tikv_raw_expire, "", 0, 0, 0);
+            try_redis_command!(ctx, "pexpire", tikv_raw_pexpire, "", 0, 0, 0);
+            try_redis_command!(ctx, "expireat", tikv_raw_expireat, "", 0, 0, 0);
+            try_redis_command!(ctx, "pexpireat", tikv_raw_pexpireat, "", 0, 0, 0);
+            try_redis_command!(ctx, "ttl", tikv_raw_ttl, "", 0, 0, 0);
+            try_redis_command!(ctx, "pttl", tikv_raw_pttl, "", 0, 0, 0);
+            try_redis_command!(ctx, "persist", tikv_raw_persist, "", 0, 0, 0);
         }
     }
     Status::Ok
@@ -11,6 +11,8 @@ extern crate tikv_client;
 extern crate tokio;
 extern crate gperftools;
 extern crate thiserror;
+extern crate lz4_flex;
+extern crate flate2;
 
 mod init;
 mod encoding;
@@ -34,20 +36,48 @@ redis_module! {
         ["tikv.conn", tikv_connect, "", 0, 0, 0],
         ["tikv.close", tikv_close, "", 0, 0, 0],
         ["tikv.status", tikv_status, "", 0, 0, 0],
+        // Generic Commands
+        ["tikv.type", tikv_type, "", 0, 0, 0],
         // String Commands
         ["tikv.get", tikv_raw_get, "", 0, 0, 0],
         ["tikv.set", tikv_raw_set, "", 0, 0, 0],
         ["tikv.setnx", tikv_raw_setnx, "", 0, 0, 0],
+        ["tikv.getset", tikv_raw_getset, "", 0, 0, 0],
+        ["tikv.getdel", tikv_raw_getdel, "", 0, 0, 0],
+        ["tikv.getex", tikv_raw_getex, "", 0, 0, 0],
+        ["tikv.append", tikv_raw_append, "", 0, 0, 0],
+        ["tikv.strlen", tikv_raw_strlen, "", 0, 0, 0],
+        ["tikv.getrange", tikv_raw_getrange, "", 0, 0, 0],
+        ["tikv.setrange", tikv_raw_setrange, "", 0, 0, 0],
         ["tikv.del", tikv_raw_del, "", 0, 0, 0],
         ["tikv.incr", tikv_raw_incr, "", 0, 0, 0],
         ["tikv.incrby", tikv_raw_incrby, "", 0, 0, 0],
         ["tikv.decr", tikv_raw_decr, "", 0, 0, 0],
         ["tikv.decrby", tikv_raw_decrby, "", 0, 0, 0],
+        ["tikv.incrbyfloat", tikv_raw_incrbyfloat, "", 0, 0, 0],
         ["tikv.mget", tikv_raw_batch_get, "", 0, 0, 0],
         ["tikv.mset", tikv_raw_batch_set, "", 0, 0, 0],
         ["tikv.redis_set", tikv_redis_set, "", 0, 0, 0],
         ["tikv.expire", tikv_raw_expire, "", 0, 0, 0],
         ["tikv.ttl", tikv_raw_ttl, "", 0, 0, 0],
+        ["tikv.pexpire", tikv_raw_pexpire, "", 0, 0, 0],
+        ["tikv.expireat", tikv_raw_expireat, "", 0, 0, 0],
+        ["tikv.pexpireat", tikv_raw_pexpireat, "", 0, 0, 0],
+        ["tikv.pttl", tikv_raw_pttl, "", 0, 0, 0],
+        ["tikv.persist", tikv_raw_persist, "", 0, 0, 0],
+        // Bitmap Commands
+        ["tikv.setbit", tikv_setbit, "", 0, 0, 0],
+        ["tikv.getbit", tikv_getbit, "", 0, 0, 0],
+        ["tikv.bitcount", tikv_bitcount, "", 0, 0, 0],
+        ["tikv.bitpos", tikv_bitpos, "", 0, 0, 0],
+        ["tikv.bitop", tikv_bitop, "", 0, 0, 0],
+        // HyperLogLog Commands
+        ["tikv.pfadd", tikv_pfadd, "", 0, 0, 0],
+        ["tikv.pfcount", tikv_pfcount, "", 0, 0, 0],
+        ["tikv.pfmerge", tikv_pfmerge, "", 0, 0, 0],
+        // Sharded Counter Commands
+        ["tikv.cincr", tikv_cincr, "", 0, 0, 0],
+        ["tikv.cgetcount", tikv_cgetcount, "", 0, 0, 0],
         // Cached String Commands
         ["tikv.cget", tikv_raw_cached_get, "", 0, 0, 0],
         ["tikv.cset", tikv_raw_cached_set, "", 0, 0, 0],
@@ -62,6 +92,20 @@ redis_module! {
         ["tikv.hmget", tikv_hmget, "", 0, 0, 0],
         ["tikv.hexists", tikv_hexists, "", 0, 0, 0],
         ["tikv.hdel", tikv_hdel, "", 0, 0, 0],
+        ["tikv.hlen", tikv_hlen, "", 0, 0, 0],
+        ["tikv.hsetnx", tikv_hsetnx, "", 0, 0, 0],
+        ["tikv.hincrby", tikv_hincrby, "", 0, 0, 0],
+        ["tikv.hincrbyfloat", tikv_hincrbyfloat, "", 0, 0, 0],
+        ["tikv.hstrlen", tikv_hstrlen, "", 0, 0, 0],
+        ["tikv.hscan", tikv_hscan, "", 0, 0, 0],
+        ["tikv.hexpire", tikv_hexpire, "", 0, 0, 0],
+        ["tikv.hpexpire", tikv_hpexpire, "", 0, 0, 0],
+        ["tikv.hexpireat", tikv_hexpireat, "", 0, 0, 0],
+        ["tikv.hpexpireat", tikv_hpexpireat, "", 0, 0, 0],
+        ["tikv.hpersist", tikv_hpersist, "", 0, 0, 0],
+        ["tikv.httl", tikv_httl, "", 0, 0, 0],
+        ["tikv.hpttl", tikv_hpttl, "", 0, 0, 0],
+        ["tikv.hrandfield", tikv_hrandfield, "", 0, 0, 0],
         // TiKV list series
         ["tikv.lpush", tikv_lpush, "", 0, 0, 0],
         ["tikv.rpush", tikv_rpush, "", 0, 0, 0],
@@ -84,5 +128,10 @@ redis_module! {
         ["tikv.dscan", tikv_rawkv_dscan, "", 0, 0, 0],
         ["tikv.lscan", tikv_rawkv_lscan, "", 0, 0, 0],
         ["tikv.wscan", tikv_rawkv_wscan, "", 0, 0, 0],
+        ["tikv.migrate", tikv_migrate, "", 0, 0, 0],
+        ["tikv.flushdb", tikv_flushdb, "", 0, 0, 0],
+        ["tikv.swapdb", tikv_swapdb, "", 0, 0, 0],
+        ["tikv.encodekey", tikv_encode_key, "", 0, 0, 0],
+        ["tikv.decodekey", tikv_decode_key, "", 0, 0, 0],
     ],
 }
@@ -1,9 +1,9 @@
 use crate::{init::{GLOBAL_RT_FAST, ASYNC_EXECUTE_MODE}, commands::errors::AsyncResult};
 use redis_module::{
-    BlockedClient, Context, RedisValue, ThreadSafeContext,
-    redisraw::bindings::RedisModule_GetClientId, RedisError, RedisResult, RedisModule_GetContextFlags, REDISMODULE_CTX_FLAGS_LUA,
+    BlockedClient, Context, RedisValue, RedisString, ThreadSafeContext,
+    redisraw::bindings::{RedisModule_GetClientId, RedisModule_GetSelectedDb}, RedisError, RedisResult, RedisModule_GetContextFlags, REDISMODULE_CTX_FLAGS_LUA,
 };
-use std::{future::Future, sync::Arc};
+use std::{future::Future, sync::{Arc, atomic::{AtomicU64, Ordering}}, time::{SystemTime, UNIX_EPOCH}};
 use tokio::{
     time::Duration, task::JoinHandle, sync::RwLock,
 };
@@ -12,6 +12,27 @@ lazy_static! {
     static ref LUA_SCRIPT_LOCK: Arc<RwLock<()>> = Arc::new(RwLock::new(()));
 }
 
+tokio::task_local! {
+    // Selected DB of the client which the async task is executing for
+    static CURRENT_DB: u32;
+}
+
+// Get selected DB in async task, returns 0 if not in a command's task.
+pub fn get_current_db() -> u32 {
+    CURRENT_DB.try_with(|db| *db).unwrap_or(0)
+}
+
+// Bind client's selected DB to the future, so the KeyEncoder created in it
+// will encode keys into the selected DB.
+pub fn with_selected_db<F: Future>(ctx: &Context, future: F) -> impl Future<Output = F::Output> {
+    with_db(get_selected_db(ctx), future)
+}
+
+// Run future with given DB selected, for commands that work on a fixed DB.
+pub fn with_db<F: Future>(db: u32, future: F) -> impl Future<Output = F::Output> {
+    CURRENT_DB.scope(db, future)
+}
+
 pub fn resp_ok() -> RedisValue {
     RedisValue::SimpleStringStatic("OK")
 }
@@ -24,6 +45,182 @@ pub fn resp_int(val: i64) -> RedisValue {
     RedisValue::Integer(val)
 }
 
+// Read next argument as raw bytes, binary data will be kept untouched.
+pub trait NextBytes {
+    fn next_bytes(&mut self) -> Result<Vec<u8>, RedisError>;
+}
+
+impl<T: Iterator<Item = RedisString>> NextBytes for T {
+    fn next_bytes(&mut self) -> Result<Vec<u8>, RedisError> {
+        match self.next() {
+            Some(arg) => Ok(arg.as_slice().to_vec()),
+            None => Err(RedisError::WrongArity),
+        }
+    }
+}
+
+// Parse integer as Redis does: no spaces, no `+` sign, no leading zeros and
+// must be in range of i64.
+pub fn parse_redis_int(data: &[u8]) -> Option<i64> {
+    let digits = data.strip_prefix(b"-").unwrap_or(data);
+    if digits.is_empty() || !digits.iter().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    if digits[0] == b'0' && (digits.len() > 1 || digits.len() < data.len()) {
+        return None;
+    }
+    std::str::from_utf8(data).ok()?.parse::<i64>().ok()
+}
+
+// Parse float as Redis does: no spaces and must not be NaN or Infinity.
+pub fn parse_redis_float(data: &[u8]) -> Option<f64> {
+    let s = std::str::from_utf8(data).ok()?;
+    if s.is_empty() || s.trim() != s {
+        return None;
+    }
+    let val = s.parse::<f64>().ok()?;
+    if val.is_finite() {
+        Some(val)
+    } else {
+        None
+    }
+}
+
+// Significant decimal digits that f64 can always represent
+const FLOAT_DIGITS: usize = 15;
+// Redis prints INCRBYFLOAT result with `%.17Lf`
+const FLOAT_DECIMALS: i32 = 17;
+
+// Format float in Redis's INCRBYFLOAT style, no exponent and no trailing zeros.
+// Redis computes in long double, which hides f64 rounding errors such as
+// 0.1 + 0.2, so value is rounded to `FLOAT_DIGITS` significant digits first.
+pub fn format_redis_float(val: f64) -> String {
+    if val == 0.0 {
+        return String::from("0");
+    }
+    // Scientific format: `[-]d.ddd...e[-]x`
+    let sci = format!("{:.*e}", FLOAT_DIGITS - 1, val);
+    let (mantissa, exp) = sci.split_once('e').unwrap();
+    let (sign, mantissa) = match mantissa.strip_prefix('-') {
+        Some(m) => ("-", m),
+        None => ("", mantissa),
+    };
+    // Number of digits before decimal point, may be negative
+    let point = exp.parse::<i32>().unwrap() + 1;
+    let mut digits: String = mantissa.chars().filter(|c| *c != '.').collect();
+    digits.truncate((point + FLOAT_DECIMALS).max(0) as usize);
+    while digits.ends_with('0') {
+        digits.pop();
+    }
+    if digits.is_empty() {
+        return String::from("0");
+    }
+    let mut ret = String::from(sign);
+    if point <= 0 {
+        ret.push_str("0.");
+        ret.push_str(&"0".repeat(-point as usize));
+        ret.push_str(&digits);
+    } else if point as usize >= digits.len() {
+        ret.push_str(&digits);
+        ret.push_str(&"0".repeat(point as usize - digits.len()));
+    } else {
+        ret.push_str(&digits[..point as usize]);
+        ret.push('.');
+        ret.push_str(&digits[point as usize..]);
+    }
+    ret
+}
+
+// Match glob-style pattern as Redis does, supports `*`, `?`, `[...]` with `^`
+// and ranges, and `\` to escape special characters.
+pub fn glob_match(pattern: &[u8], string: &[u8]) -> bool {
+    let plen = pattern.len();
+    let mut p = 0;
+    let mut s = 0;
+    while p < plen {
+        match pattern[p] {
+            b'*' => {
+                while p + 1 < plen && pattern[p + 1] == b'*' {
+                    p += 1;
+                }
+                if p + 1 == plen {
+                    return true;
+                }
+                return (s..=string.len()).any(|i| glob_match(&pattern[p + 1..], &string[i..]));
+            }
+            b'?' => {
+                if s >= string.len() {
+                    return false;
+                }
+                s += 1;
+            }
+            b'[' => {
+                if s >= string.len() {
+                    return false;
+                }
+                p += 1;
+                let not = p < plen && pattern[p] == b'^';
+                if not {
+                    p += 1;
+                }
+                let mut matched = false;
+                while p < plen {
+                    if pattern[p] == b'\\' && plen - p >= 2 {
+                        p += 1;
+                        matched |= pattern[p] == string[s];
+                    } else if pattern[p] == b']' {
+                        break;
+                    } else if plen - p >= 3 && pattern[p + 1] == b'-' {
+                        let (start, end) = if pattern[p] <= pattern[p + 2] {
+                            (pattern[p], pattern[p + 2])
+                        } else {
+                            (pattern[p + 2], pattern[p])
+                        };
+                        matched |= (start..=end).contains(&string[s]);
+                        p += 2;
+                    } else {
+                        matched |= pattern[p] == string[s];
+                    }
+                    p += 1;
+                }
+                if matched == not {
+                    return false;
+                }
+                s += 1;
+            }
+            c => {
+                let c = if c == b'\\' && p + 1 < plen {
+                    p += 1;
+                    pattern[p]
+                } else {
+                    c
+                };
+                if s >= string.len() || string[s] != c {
+                    return false;
+                }
+                s += 1;
+            }
+        }
+        p += 1;
+    }
+    s == string.len()
+}
+
+static RANDOM_STATE: AtomicU64 = AtomicU64::new(0);
+
+// Fast non-cryptographic random number (splitmix64), seeded by current time
+pub fn random_u64() -> u64 {
+    if RANDOM_STATE.load(Ordering::Relaxed) == 0 {
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_nanos() as u64);
+        let _ = RANDOM_STATE.compare_exchange(0, nanos | 1, Ordering::Relaxed, Ordering::Relaxed);
+    }
+    let seed = RANDOM_STATE.fetch_add(0x9e3779b97f4a7c15, Ordering::Relaxed);
+    let mut z = seed.wrapping_add(0x9e3779b97f4a7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
 pub async fn sleep(ms: u32) {
     tokio::time::sleep(Duration::from_millis(ms as u64)).await;
 }
@@ -85,6 +282,7 @@ pub fn async_execute<F>(ctx: &Context, future: F) -> RedisResult
 where
     F: Future<Output = AsyncResult<RedisValue>> + Send + 'static,
 {
+    let future = with_selected_db(ctx, future);
     let (sync_mode, in_script) = is_block(ctx);
     if sync_mode {
         let ret: AsyncResult<RedisValue>;
@@ -121,6 +319,11 @@ pub fn get_client_id(ctx: &Context) -> u64 {
     unsafe { RedisModule_GetClientId.unwrap()(ctx.get_raw()) }
 }
 
+#[inline]
+pub fn get_selected_db(ctx: &Context) -> u32 {
+    unsafe { RedisModule_GetSelectedDb.unwrap()(ctx.get_raw()) as u32 }
+}
+
 #[inline]
 pub fn get_context_flags(ctx: &Context) -> u32 {
     unsafe { RedisModule_GetContextFlags.unwrap()(ctx.get_raw()) as u32 }
@@ -179,3 +382,77 @@ macro_rules! try_redis_command {
         }
     }};
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_redis_int() {
+        assert_eq!(parse_redis_int(b"0"), Some(0));
+        assert_eq!(parse_redis_int(b"123"), Some(123));
+        assert_eq!(parse_redis_int(b"-123"), Some(-123));
+        assert_eq!(parse_redis_int(b"9223372036854775807"), Some(i64::MAX));
+        assert_eq!(parse_redis_int(b"-9223372036854775808"), Some(i64::MIN));
+        assert_eq!(parse_redis_int(b"9223372036854775808"), None);
+        assert_eq!(parse_redis_int(b""), None);
+        assert_eq!(parse_redis_int(b"-"), None);
+        assert_eq!(parse_redis_int(b"-0"), None);
+        assert_eq!(parse_redis_int(b"007"), None);
+        assert_eq!(parse_redis_int(b"+1"), None);
+        assert_eq!(parse_redis_int(b" 1"), None);
+        assert_eq!(parse_redis_int(b"1 "), None);
+        assert_eq!(parse_redis_int(b"1.0"), None);
+    }
+
+    #[test]
+    fn test_parse_redis_float() {
+        assert_eq!(parse_redis_float(b"10.50"), Some(10.5));
+        assert_eq!(parse_redis_float(b"5.0e3"), Some(5000.0));
+        assert_eq!(parse_redis_float(b"-1"), Some(-1.0));
+        assert_eq!(parse_redis_float(b""), None);
+        assert_eq!(parse_redis_float(b" 1.5"), None);
+        assert_eq!(parse_redis_float(b"abc"), None);
+        assert_eq!(parse_redis_float(b"inf"), None);
+        assert_eq!(parse_redis_float(b"nan"), None);
+    }
+
+    #[test]
+    fn test_format_redis_float() {
+        // Outputs of Redis INCRBYFLOAT
+        assert_eq!(format_redis_float(0.1 + 0.2), "0.3");
+        assert_eq!(format_redis_float(10.5 + 0.1), "10.6");
+        assert_eq!(format_redis_float(10.5 + 0.1 - 5.0), "5.6");
+        assert_eq!(format_redis_float(5.0e3 + 2.0e2), "5200");
+        assert_eq!(format_redis_float(3.0), "3");
+        assert_eq!(format_redis_float(0.0), "0");
+        assert_eq!(format_redis_float(-1.5), "-1.5");
+        assert_eq!(format_redis_float(0.000015), "0.000015");
+        assert_eq!(format_redis_float(1e20), "100000000000000000000");
+        assert_eq!(format_redis_float(1e-20), "0");
+        assert_eq!(format_redis_float(123456.789), "123456.789");
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match(b"*", b""));
+        assert!(glob_match(b"*", b"anything"));
+        assert!(glob_match(b"h?llo", b"hello"));
+        assert!(!glob_match(b"h?llo", b"hllo"));
+        assert!(glob_match(b"h*llo", b"heeeello"));
+        assert!(glob_match(b"h[ae]llo", b"hallo"));
+        assert!(!glob_match(b"h[ae]llo", b"hillo"));
+        assert!(glob_match(b"h[^e]llo", b"hallo"));
+        assert!(!glob_match(b"h[^e]llo", b"hello"));
+        assert!(glob_match(b"h[a-b]llo", b"hbllo"));
+        assert!(glob_match(b"h[b-a]llo", b"hallo"));
+        assert!(!glob_match(b"h[a-b]llo", b"hcllo"));
+        assert!(glob_match(b"h\\*llo", b"h*llo"));
+        assert!(!glob_match(b"h\\*llo", b"hello"));
+        assert!(glob_match(b"[\\]]", b"]"));
+        assert!(glob_match(b"user:*:name", b"user:1000:name"));
+        assert!(!glob_match(b"user:*:name", b"user:1000:age"));
+        assert!(!glob_match(b"abc", b"abcd"));
+        assert!(glob_match(b"a**c", b"abbbc"));
+    }
+}